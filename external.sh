@@ -1,10 +1,10 @@
 #!/usr/bin/env bash
 set -e
 if [[ $OSTYPE == 'darwin'* ]]; then
-  brew install sdl2 sdl2_gfx sdl2_ttf
+  brew install sdl2 sdl2_gfx sdl2_mixer sdl2_ttf
 elif [[ $OSTYPE == 'linux-gnu'* ]]; then
   sudo apt-get update
-  sudo apt-get install -y libsdl2-dev libsdl2-gfx-dev libsdl2-ttf-dev
+  sudo apt-get install -y libsdl2-dev libsdl2-gfx-dev libsdl2-mixer-dev libsdl2-ttf-dev
 fi
 rm -rf pinmame
 # Pinned for reproducible builds. The bindgen allowlist in build.rs keeps the
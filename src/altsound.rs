@@ -0,0 +1,358 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use log::{debug, info, warn};
+use sdl2::mixer::{self, Channel, Chunk, InitFlag, Sdl2MixerContext};
+
+// number of mixer channels reserved for the logical CHANNEL column of the csv
+const LOGICAL_CHANNELS: i32 = 8;
+const MIXER_CHANNELS: i32 = 32;
+// a LOOP value of 100 means "loop forever" in AltSound packages
+const LOOP_FOREVER: u32 = 100;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AltSoundMode {
+    // only play the replacement samples, the rom audio is muted
+    Replace,
+    // play the replacement samples on top of the rom audio
+    Mix,
+}
+
+impl std::str::FromStr for AltSoundMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "replace" => Ok(AltSoundMode::Replace),
+            "mix" => Ok(AltSoundMode::Mix),
+            other => Err(format!("Unknown altsound mode: {}", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AltSoundSample {
+    pub id: u32,
+    pub channel: Option<i32>,
+    pub duck: u8,
+    pub gain: u8,
+    pub looped: bool,
+    pub stop: bool,
+    pub name: String,
+    pub file: PathBuf,
+}
+
+// Parses an altsound.csv file, the columns are
+// "ID","CHANNEL","DUCK","GAIN","LOOP","STOP","NAME","FNAME",...
+// Sample paths are resolved relative to the directory of the csv file.
+pub fn load_csv(path: &Path) -> Result<Vec<AltSoundSample>, String> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+    Ok(parse_csv(&content, path))
+}
+
+fn parse_csv(content: &str, path: &Path) -> Vec<AltSoundSample> {
+    let base = path.parent().unwrap_or(Path::new("."));
+    let mut samples = Vec::new();
+    for (line_no, line) in content.lines().enumerate().skip(1) {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let fields = split_csv_line(line);
+        if fields.len() < 8 {
+            warn!(
+                "{}:{}: expected at least 8 columns, got {}",
+                path.display(),
+                line_no + 1,
+                fields.len()
+            );
+            continue;
+        }
+        let id = match parse_number(&fields[0]) {
+            Some(id) => id,
+            None => {
+                warn!(
+                    "{}:{}: invalid sound id {}",
+                    path.display(),
+                    line_no + 1,
+                    fields[0]
+                );
+                continue;
+            }
+        };
+        samples.push(AltSoundSample {
+            id,
+            channel: parse_number(&fields[1]).map(|c| c as i32),
+            duck: parse_number(&fields[2]).unwrap_or(100).min(100) as u8,
+            gain: parse_number(&fields[3]).unwrap_or(100).min(100) as u8,
+            looped: parse_number(&fields[4]) == Some(LOOP_FOREVER),
+            stop: parse_number(&fields[5]).unwrap_or(0) != 0,
+            name: fields[6].clone(),
+            file: base.join(&fields[7]),
+        });
+    }
+    samples
+}
+
+fn split_csv_line(line: &str) -> Vec<String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    for c in line.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            _ => field.push(c),
+        }
+    }
+    fields.push(field);
+    fields.iter().map(|f| f.trim().to_string()).collect()
+}
+
+fn parse_number(field: &str) -> Option<u32> {
+    match field
+        .strip_prefix("0x")
+        .or_else(|| field.strip_prefix("0X"))
+    {
+        Some(hex) => u32::from_str_radix(hex, 16).ok(),
+        None => field.parse().ok(),
+    }
+}
+
+pub struct AltSound {
+    mode: AltSoundMode,
+    samples: HashMap<u32, Vec<AltSoundSample>>,
+    // round robin index for ids that have multiple samples
+    next_sample: HashMap<u32, usize>,
+    chunks: HashMap<PathBuf, Chunk>,
+    previous_cmd: Option<u32>,
+    // channels that are currently ducking the others and by how much
+    ducking: Vec<(Channel, u8)>,
+    _mixer_context: Option<Sdl2MixerContext>,
+}
+
+// Some boards (eg WPC DCS) send 16 bit commands as two bytes, the pair counts when there is
+// a sample for it
+fn combine_command(
+    previous_cmd: &mut Option<u32>,
+    cmd: u32,
+    samples: &HashMap<u32, Vec<AltSoundSample>>,
+) -> u32 {
+    let combined = previous_cmd.map(|previous| (previous << 8) | cmd);
+    match combined {
+        Some(combined) if samples.contains_key(&combined) => {
+            *previous_cmd = None;
+            combined
+        }
+        _ => {
+            *previous_cmd = Some(cmd);
+            cmd
+        }
+    }
+}
+
+impl AltSound {
+    pub fn load(path: &Path, mode: AltSoundMode) -> Result<AltSound, String> {
+        let csv = if path.is_dir() {
+            path.join("altsound.csv")
+        } else {
+            path.to_path_buf()
+        };
+        let mut samples: HashMap<u32, Vec<AltSoundSample>> = HashMap::new();
+        for sample in load_csv(&csv)? {
+            samples.entry(sample.id).or_default().push(sample);
+        }
+        info!(
+            "Loaded {} altsound ids from {}",
+            samples.len(),
+            csv.display()
+        );
+
+        let mixer_context = match mixer::init(InitFlag::OGG | InitFlag::MP3) {
+            Ok(context) => Some(context),
+            Err(e) => {
+                warn!(
+                    "altsound: mixer init failed, only wav samples will play: {}",
+                    e
+                );
+                None
+            }
+        };
+        mixer::open_audio(44_100, mixer::DEFAULT_FORMAT, 2, 1_024)?;
+        mixer::allocate_channels(MIXER_CHANNELS);
+        // keep the logical channels out of the pool used for samples without a channel
+        mixer::reserve_channels(LOGICAL_CHANNELS);
+
+        Ok(AltSound {
+            mode,
+            samples,
+            next_sample: HashMap::new(),
+            chunks: HashMap::new(),
+            previous_cmd: None,
+            ducking: Vec::new(),
+            _mixer_context: mixer_context,
+        })
+    }
+
+    pub fn mode(&self) -> AltSoundMode {
+        self.mode
+    }
+
+    pub fn handle_command(&mut self, board_no: i32, cmd: i32) {
+        let id = combine_command(&mut self.previous_cmd, cmd as u32, &self.samples);
+
+        let Some(candidates) = self.samples.get(&id) else {
+            debug!(
+                "altsound: no sample for board {} cmd 0x{:04x}",
+                board_no, id
+            );
+            return;
+        };
+        let index = self.next_sample.entry(id).or_insert(0);
+        let sample = candidates[*index % candidates.len()].clone();
+        *index += 1;
+
+        if let Err(e) = self.play(&sample) {
+            warn!("altsound: could not play {}: {}", sample.file.display(), e);
+        }
+    }
+
+    fn play(&mut self, sample: &AltSoundSample) -> Result<(), String> {
+        if sample.stop {
+            Channel::all().halt();
+            self.ducking.clear();
+        }
+        if !self.chunks.contains_key(&sample.file) {
+            let chunk = Chunk::from_file(&sample.file)?;
+            self.chunks.insert(sample.file.clone(), chunk);
+        }
+        let chunk = self.chunks.get_mut(&sample.file).unwrap();
+        chunk.set_volume(mixer::MAX_VOLUME * sample.gain as i32 / 100);
+
+        let target = match sample.channel {
+            Some(channel) if channel < LOGICAL_CHANNELS => {
+                // a new sample on a logical channel replaces the previous one
+                Channel(channel).halt();
+                Channel(channel)
+            }
+            _ => Channel::all(),
+        };
+        let loops = if sample.looped { -1 } else { 0 };
+        let channel = target.play(chunk, loops)?;
+        debug!(
+            "altsound: playing 0x{:04x} {} on channel {}",
+            sample.id, sample.name, channel.0
+        );
+        self.ducking.retain(|(c, _)| *c != channel);
+        if sample.duck < 100 {
+            self.ducking.push((channel, sample.duck));
+        }
+        Ok(())
+    }
+
+    // Updates ducking, should be called every frame. Returns the volume in percent
+    // the rom audio should be played at.
+    pub fn update(&mut self) -> u8 {
+        self.ducking.retain(|(channel, _)| channel.is_playing());
+        let duck = self
+            .ducking
+            .iter()
+            .map(|(_, duck)| *duck)
+            .min()
+            .unwrap_or(100);
+        for index in 0..MIXER_CHANNELS {
+            let channel = Channel(index);
+            let volume = if self.ducking.iter().any(|(c, _)| *c == channel) {
+                mixer::MAX_VOLUME
+            } else {
+                mixer::MAX_VOLUME * duck as i32 / 100
+            };
+            channel.set_volume(volume);
+        }
+        match self.mode {
+            AltSoundMode::Replace => 0,
+            AltSoundMode::Mix => duck,
+        }
+    }
+}
+
+impl Drop for AltSound {
+    fn drop(&mut self) {
+        Channel::all().halt();
+        mixer::close_audio();
+    }
+}
+
+// Applies the rom audio volume set by the altsound ducking
+pub fn scale_samples(samples: &[i16], volume: u8) -> Vec<i16> {
+    samples
+        .iter()
+        .map(|sample| (*sample as i32 * volume as i32 / 100) as i16)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CSV: &str = r#""ID","CHANNEL","DUCK","GAIN","LOOP","STOP","NAME","FNAME","GROUP","SHAKER","SERIAL","PRELOAD"
+"0x0001","0","100","80","100","0","music","music/main.ogg","1","","",""
+"0x03e8","","40","100","0","1","callout","voice/callout.wav","2","","",""
+"0x0002","1","","","","","","broken.wav"
+"nope","1","50","50","0","0","bad id","bad.wav","1","","",""
+"#;
+
+    fn samples() -> Vec<AltSoundSample> {
+        parse_csv(CSV, Path::new("/sounds/t2/altsound.csv"))
+    }
+
+    #[test]
+    fn parses_loop_and_duck_columns() {
+        let samples = samples();
+        assert_eq!(samples.len(), 3);
+
+        let music = &samples[0];
+        assert_eq!(music.id, 1);
+        assert_eq!(music.channel, Some(0));
+        assert_eq!(music.duck, 100);
+        assert_eq!(music.gain, 80);
+        assert!(music.looped);
+        assert!(!music.stop);
+        assert_eq!(music.file, Path::new("/sounds/t2/music/main.ogg"));
+
+        let callout = &samples[1];
+        assert_eq!(callout.id, 0x03e8);
+        assert_eq!(callout.channel, None);
+        assert_eq!(callout.duck, 40);
+        assert!(!callout.looped);
+        assert!(callout.stop);
+    }
+
+    #[test]
+    fn empty_columns_use_defaults() {
+        let broken = &samples()[2];
+        assert_eq!(broken.duck, 100);
+        assert_eq!(broken.gain, 100);
+        assert!(!broken.looped);
+        assert!(!broken.stop);
+    }
+
+    #[test]
+    fn combines_two_byte_commands() {
+        let mut by_id: HashMap<u32, Vec<AltSoundSample>> = HashMap::new();
+        for sample in samples() {
+            by_id.entry(sample.id).or_default().push(sample);
+        }
+        let mut previous_cmd = None;
+        assert_eq!(combine_command(&mut previous_cmd, 0x03, &by_id), 0x03);
+        assert_eq!(combine_command(&mut previous_cmd, 0xe8, &by_id), 0x03e8);
+        // the pair is used up
+        assert_eq!(previous_cmd, None);
+        // without a sample for the pair the second byte stands alone
+        assert_eq!(combine_command(&mut previous_cmd, 0x00, &by_id), 0x00);
+        assert_eq!(combine_command(&mut previous_cmd, 0x05, &by_id), 0x05);
+        assert_eq!(previous_cmd, Some(0x05));
+    }
+}
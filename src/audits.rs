@@ -0,0 +1,201 @@
+use std::path::Path;
+
+use sdl2::{pixels::Color, rect::Rect};
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{
+    cli::NvArgs,
+    db::{self, NvramEncoding, NvramField},
+    text::render_text,
+};
+
+const ROWS: usize = 11;
+const ROW_HEIGHT: u32 = 15;
+pub const AUDITS_WIDTH: u32 = 270;
+pub const AUDITS_HEIGHT: u32 = (ROWS as u32 + 1) * ROW_HEIGHT + 4;
+
+#[derive(Serialize)]
+struct DecodedField<'a> {
+    name: &'a str,
+    address: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    value: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+pub fn decode(field: &NvramField, nvram: &[u8]) -> Result<Value, String> {
+    let start = field.address as usize;
+    let end = start + field.length as usize;
+    let bytes = nvram.get(start..end).ok_or_else(|| {
+        format!(
+            "0x{:04x}-0x{:04x} is outside the {} byte nvram",
+            start,
+            end,
+            nvram.len()
+        )
+    })?;
+    match field.encoding {
+        NvramEncoding::Int => {
+            let value = bytes.iter().try_fold(0u64, |value, byte| {
+                value.checked_mul(256)?.checked_add(*byte as u64)
+            });
+            value.map(Value::from).ok_or_else(|| "too long".to_string())
+        }
+        NvramEncoding::Bcd => {
+            let mut value = 0u64;
+            for byte in bytes {
+                let (high, low) = (byte >> 4, byte & 0x0f);
+                if high > 9 || low > 9 {
+                    return Err(format!("invalid bcd {:02x}", byte));
+                }
+                value = value
+                    .checked_mul(100)
+                    .and_then(|value| value.checked_add((high * 10 + low) as u64))
+                    .ok_or_else(|| "too long".to_string())?;
+            }
+            Ok(Value::from(value))
+        }
+        NvramEncoding::Ascii => {
+            let text: String = bytes
+                .iter()
+                .map(|b| {
+                    if b.is_ascii_graphic() || *b == b' ' {
+                        *b as char
+                    } else {
+                        '?'
+                    }
+                })
+                .collect();
+            Ok(Value::from(text.trim_end()))
+        }
+        NvramEncoding::Hex => Ok(Value::from(
+            bytes
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect::<Vec<_>>()
+                .join(" "),
+        )),
+    }
+}
+
+fn describe(field: &NvramField, nvram: &[u8]) -> String {
+    match decode(field, nvram) {
+        Ok(Value::String(text)) => text,
+        Ok(value) => value.to_string(),
+        Err(e) => format!("({})", e),
+    }
+}
+
+pub fn to_json(fields: &[NvramField], nvram: &[u8]) -> Result<String, String> {
+    let decoded: Vec<DecodedField> = fields
+        .iter()
+        .map(|field| {
+            let (value, error) = match decode(field, nvram) {
+                Ok(value) => (Some(value), None),
+                Err(e) => (None, Some(e)),
+            };
+            DecodedField {
+                name: &field.name,
+                address: field.address,
+                value,
+                error,
+            }
+        })
+        .collect();
+    serde_json::to_string_pretty(&decoded).map_err(|e| e.to_string())
+}
+
+pub fn export(path: &Path, fields: &[NvramField], nvram: &[u8]) -> Result<(), String> {
+    let json = to_json(fields, nvram)?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    std::fs::write(path, json).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+// Decodes an nvram file without running the rom. The addresses of the fields are offsets
+// in the file.
+pub fn inspect(args: &NvArgs) -> Result<(), String> {
+    let nvram = std::fs::read(&args.file)
+        .map_err(|e| format!("Could not read {}: {}", args.file.display(), e))?;
+    // dumps are named <rom>-<timestamp>.nv
+    let rom = match &args.rom {
+        Some(rom) => rom.clone(),
+        None => args
+            .file
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| stem.split('-').next())
+            .ok_or_else(|| format!("Can't tell the rom from {}", args.file.display()))?
+            .to_string(),
+    };
+    let pinmame_path = dirs::home_dir()
+        .ok_or("No home directory")?
+        .join(".pinmame");
+    let machine = db::load_machine(&db::machine_dirs(&pinmame_path), &rom, None)?
+        .ok_or_else(|| format!("No machine definition found for {}", rom))?
+        .machine;
+    if machine.nvram_fields.is_empty() {
+        return Err(format!("The definition for {} has no nvram_fields", rom));
+    }
+    if args.json {
+        println!("{}", to_json(&machine.nvram_fields, &nvram)?);
+    } else {
+        for field in &machine.nvram_fields {
+            println!(
+                "{} (0x{:04x}): {}",
+                field.name,
+                field.address,
+                describe(field, &nvram)
+            );
+        }
+    }
+    Ok(())
+}
+
+pub fn render_audits(
+    at_x: u32,
+    at_y: u32,
+    fields: &[NvramField],
+    nvram: &[u8],
+    canvas: &mut sdl2::render::Canvas<sdl2::video::Window>,
+    font: &sdl2::ttf::Font<'_, '_>,
+) -> Result<(), String> {
+    canvas.set_draw_color(Color::RGB(20, 20, 20));
+    canvas.fill_rect(Rect::new(
+        at_x as i32,
+        at_y as i32,
+        AUDITS_WIDTH,
+        AUDITS_HEIGHT,
+    ))?;
+    let title = if fields.is_empty() {
+        "Audits: no nvram_fields defined".to_string()
+    } else if fields.len() > ROWS {
+        format!("Audits: first {} of {}", ROWS, fields.len())
+    } else {
+        "Audits".to_string()
+    };
+    render_text(
+        at_x as i32 + 2,
+        at_y as i32,
+        &title,
+        Color::RGB(150, 150, 150),
+        canvas,
+        font,
+    )?;
+    for (line, field) in fields.iter().take(ROWS).enumerate() {
+        let y = (at_y + ROW_HEIGHT * (line as u32 + 1)) as i32;
+        let text = format!("{}: {}", field.name, describe(field, nvram));
+        render_text(
+            at_x as i32 + 2,
+            y,
+            &text,
+            Color::RGB(200, 200, 200),
+            canvas,
+            font,
+        )?;
+    }
+    Ok(())
+}
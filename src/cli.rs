@@ -0,0 +1,151 @@
+use std::path::PathBuf;
+
+use crate::altsound::AltSoundMode;
+
+const USAGE: &str = "Usage:
+  pinmame-test [--altsound[=replace|mix]] [--altsound-path <dir or csv>] [--switch-script <toml>]
+               [--pulse-ms <ms>] [--nvram <dump>]
+  pinmame-test import <wpc-emu|vpe> <input> [<output.toml>]
+  pinmame-test nv <file.nv> [<rom>] [--json]";
+
+pub enum Command {
+    Run(Options),
+    Import(ImportArgs),
+    Nv(NvArgs),
+}
+
+const DEFAULT_PULSE_MS: u64 = 50;
+
+#[derive(Debug)]
+pub struct Options {
+    pub altsound: Option<AltSoundMode>,
+    pub altsound_path: Option<PathBuf>,
+    pub switch_script: Option<PathBuf>,
+    // how long a switch is closed when pulsed with the middle or shift + left mouse button
+    pub pulse_ms: u64,
+    // nvram dump to boot the rom with
+    pub nvram: Option<PathBuf>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            altsound: None,
+            altsound_path: None,
+            switch_script: None,
+            pulse_ms: DEFAULT_PULSE_MS,
+            nvram: None,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ImportFormat {
+    // game definitions from https://github.com/neophob/wpc-emu, saved as json
+    WpcEmu,
+    // C# game tables from https://github.com/VisualPinball/VisualPinball.Engine.PinMAME
+    Vpe,
+}
+
+pub struct ImportArgs {
+    pub format: ImportFormat,
+    pub input: PathBuf,
+    // stdout if not set
+    pub output: Option<PathBuf>,
+}
+
+pub struct NvArgs {
+    pub file: PathBuf,
+    // taken from the file name if not set
+    pub rom: Option<String>,
+    pub json: bool,
+}
+
+pub fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Command, String> {
+    let mut args = args.into_iter().peekable();
+    if args.peek().map(String::as_str) == Some("import") {
+        args.next();
+        return parse_import_args(args).map(Command::Import);
+    }
+    if args.peek().map(String::as_str) == Some("nv") {
+        args.next();
+        return parse_nv_args(args).map(Command::Nv);
+    }
+
+    let mut options = Options::default();
+    while let Some(arg) = args.next() {
+        match arg.split_once('=') {
+            Some(("--altsound", mode)) => options.altsound = Some(mode.parse()?),
+            _ => match arg.as_str() {
+                "--altsound" => options.altsound = Some(AltSoundMode::Replace),
+                "--altsound-path" => {
+                    let path = args
+                        .next()
+                        .ok_or_else(|| format!("--altsound-path needs a value\n{}", USAGE))?;
+                    options.altsound_path = Some(PathBuf::from(path));
+                }
+                "--switch-script" => {
+                    let path = args
+                        .next()
+                        .ok_or_else(|| format!("--switch-script needs a value\n{}", USAGE))?;
+                    options.switch_script = Some(PathBuf::from(path));
+                }
+                "--pulse-ms" => {
+                    let ms = args
+                        .next()
+                        .ok_or_else(|| format!("--pulse-ms needs a value\n{}", USAGE))?;
+                    options.pulse_ms = ms
+                        .parse()
+                        .map_err(|_| format!("Invalid --pulse-ms: {}\n{}", ms, USAGE))?;
+                }
+                "--nvram" => {
+                    let path = args
+                        .next()
+                        .ok_or_else(|| format!("--nvram needs a value\n{}", USAGE))?;
+                    options.nvram = Some(PathBuf::from(path));
+                }
+                "-h" | "--help" => return Err(USAGE.to_string()),
+                other => return Err(format!("Unknown argument: {}\n{}", other, USAGE)),
+            },
+        }
+    }
+    Ok(Command::Run(options))
+}
+
+fn parse_nv_args(args: impl Iterator<Item = String>) -> Result<NvArgs, String> {
+    let mut file = None;
+    let mut rom = None;
+    let mut json = false;
+    for arg in args {
+        match arg.as_str() {
+            "--json" => json = true,
+            _ if file.is_none() => file = Some(PathBuf::from(arg)),
+            _ if rom.is_none() => rom = Some(arg),
+            other => return Err(format!("Unknown argument: {}\n{}", other, USAGE)),
+        }
+    }
+    let file = file.ok_or_else(|| format!("nv needs an nvram file\n{}", USAGE))?;
+    Ok(NvArgs { file, rom, json })
+}
+
+fn parse_import_args(mut args: impl Iterator<Item = String>) -> Result<ImportArgs, String> {
+    let format = match args.next().as_deref() {
+        Some("wpc-emu") => ImportFormat::WpcEmu,
+        Some("vpe") => ImportFormat::Vpe,
+        Some(other) => return Err(format!("Unknown import format: {}\n{}", other, USAGE)),
+        None => return Err(format!("import needs a format\n{}", USAGE)),
+    };
+    let input = args
+        .next()
+        .map(PathBuf::from)
+        .ok_or_else(|| format!("import needs an input file\n{}", USAGE))?;
+    let output = args.next().map(PathBuf::from);
+    if let Some(extra) = args.next() {
+        return Err(format!("Unknown argument: {}\n{}", extra, USAGE));
+    }
+    Ok(ImportArgs {
+        format,
+        input,
+        output,
+    })
+}
@@ -0,0 +1,128 @@
+use std::path::Path;
+
+use sdl2::{pixels::Color, rect::Rect};
+
+use crate::text::render_text;
+
+// keep at most this many bytes of console data around
+const MAX_BYTES: usize = 64 * 1024;
+const BYTES_PER_ROW: usize = 8;
+const ROWS: usize = 8;
+const ROW_HEIGHT: u32 = 15;
+const OFFSET_WIDTH: i32 = 48;
+const HEX_WIDTH: i32 = 18;
+pub const CONSOLE_WIDTH: u32 = 270;
+pub const CONSOLE_HEIGHT: u32 = ROWS as u32 * ROW_HEIGHT + ROW_HEIGHT + 4;
+
+// Raw console data as sent by the emulated machine, eg Gottlieb/Premier consoles or
+// Stern SAM serial data.
+#[derive(Default)]
+pub struct ConsoleLog {
+    data: Vec<u8>,
+    // total number of bytes received, including the ones that were dropped
+    received: usize,
+    // number of rows scrolled back from the end
+    scroll: usize,
+}
+
+impl ConsoleLog {
+    pub fn push(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+        self.received += bytes.len();
+        if self.data.len() > MAX_BYTES {
+            // drop whole rows so the offsets stay aligned
+            let excess = self.data.len() - MAX_BYTES;
+            let drop = excess.div_ceil(BYTES_PER_ROW) * BYTES_PER_ROW;
+            self.data.drain(..drop.min(self.data.len()));
+        }
+    }
+
+    pub fn scroll(&mut self, rows: i32) {
+        let max_scroll = self.total_rows().saturating_sub(ROWS);
+        self.scroll = (self.scroll as i64 + rows as i64).clamp(0, max_scroll as i64) as usize;
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        std::fs::write(path, &self.data).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    fn total_rows(&self) -> usize {
+        self.data.len().div_ceil(BYTES_PER_ROW)
+    }
+
+    // offset of the first byte we still have in memory
+    fn first_offset(&self) -> usize {
+        self.received - self.data.len()
+    }
+}
+
+pub fn render_console(
+    at_x: u32,
+    at_y: u32,
+    console: &ConsoleLog,
+    canvas: &mut sdl2::render::Canvas<sdl2::video::Window>,
+    font: &sdl2::ttf::Font<'_, '_>,
+) -> Result<(), String> {
+    canvas.set_draw_color(Color::RGB(20, 20, 20));
+    canvas.fill_rect(Rect::new(
+        at_x as i32,
+        at_y as i32,
+        CONSOLE_WIDTH,
+        CONSOLE_HEIGHT,
+    ))?;
+
+    let title_color = Color::RGB(150, 150, 150);
+    let offset_color = Color::RGB(100, 100, 160);
+    let hex_color = Color::RGB(200, 200, 200);
+    let ascii_color = Color::RGB(150, 200, 150);
+
+    let title = format!("Console: {} bytes", console.received);
+    render_text(
+        at_x as i32 + 2,
+        at_y as i32,
+        &title,
+        title_color,
+        canvas,
+        font,
+    )?;
+
+    let total_rows = console.total_rows();
+    let last_row = total_rows.saturating_sub(console.scroll);
+    let first_row = last_row.saturating_sub(ROWS);
+    for (line, row) in (first_row..last_row).enumerate() {
+        let y = (at_y + ROW_HEIGHT * (line as u32 + 1)) as i32;
+        let start = row * BYTES_PER_ROW;
+        let end = (start + BYTES_PER_ROW).min(console.data.len());
+        let bytes = &console.data[start..end];
+
+        let offset = format!("{:06x}", console.first_offset() + start);
+        render_text(at_x as i32 + 2, y, &offset, offset_color, canvas, font)?;
+
+        let mut x = at_x as i32 + 2 + OFFSET_WIDTH;
+        for byte in bytes {
+            render_text(x, y, &format!("{:02x}", byte), hex_color, canvas, font)?;
+            x += HEX_WIDTH;
+        }
+
+        let ascii: String = bytes
+            .iter()
+            .map(|b| {
+                if b.is_ascii_graphic() || *b == b' ' {
+                    *b as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        let ascii_x = at_x as i32 + 2 + OFFSET_WIDTH + HEX_WIDTH * BYTES_PER_ROW as i32 + 6;
+        render_text(ascii_x, y, &ascii, ascii_color, canvas, font)?;
+    }
+    Ok(())
+}
+
+pub fn is_over_console(x: i32, y: i32, at_x: u32, at_y: u32) -> bool {
+    Rect::new(at_x as i32, at_y as i32, CONSOLE_WIDTH, CONSOLE_HEIGHT).contains_point((x, y))
+}
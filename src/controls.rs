@@ -0,0 +1,137 @@
+use std::time::Duration;
+
+use log::{error, info};
+use sdl2::{pixels::Color, rect::Rect};
+
+use crate::{pinmame, text::render_text};
+
+// about one frame of emulated time
+const STEP: Duration = Duration::from_millis(16);
+const BUTTON_WIDTH: u32 = 60;
+const BUTTON_HEIGHT: u32 = 16;
+const MARGIN: u32 = 4;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Control {
+    PauseResume,
+    Step,
+    Reset,
+}
+
+const CONTROLS: [Control; 3] = [Control::PauseResume, Control::Step, Control::Reset];
+
+// Pausing, stepping and resetting the emulation
+#[derive(Default)]
+pub struct RunControl {
+    // pause again at this emulated time
+    step_until: Option<Duration>,
+    reset_requested: bool,
+}
+
+impl RunControl {
+    pub fn run(&mut self, control: Control, now: Duration) {
+        match control {
+            Control::PauseResume => {
+                self.step_until = None;
+                if pinmame::is_paused() {
+                    continue_emulation();
+                } else {
+                    pause_emulation();
+                }
+            }
+            // a step while running pauses first
+            Control::Step if !pinmame::is_paused() => pause_emulation(),
+            Control::Step => {
+                self.step_until = Some(now + STEP);
+                continue_emulation();
+            }
+            Control::Reset => self.reset_requested = true,
+        }
+    }
+
+    pub fn update(&mut self, now: Duration) {
+        if self.step_until.is_some_and(|until| now >= until) {
+            self.step_until = None;
+            pause_emulation();
+        }
+    }
+
+    // The reset needs the machine definition, so the main loop does it
+    pub fn take_reset_request(&mut self) -> bool {
+        std::mem::take(&mut self.reset_requested)
+    }
+
+    pub fn status(&self) -> &'static str {
+        if !pinmame::is_running() {
+            "stopped"
+        } else if self.step_until.is_some() {
+            "stepping"
+        } else if pinmame::is_paused() {
+            "paused"
+        } else {
+            "running"
+        }
+    }
+}
+
+fn pause_emulation() {
+    match pinmame::pause() {
+        Ok(()) => info!("Paused"),
+        Err(status) => error!("Could not pause: {:?}", status),
+    }
+}
+
+fn continue_emulation() {
+    if let Err(status) = pinmame::continue_() {
+        error!("Could not continue: {:?}", status);
+    }
+}
+
+fn button_rect(at_x: u32, at_y: u32, index: usize) -> Rect {
+    Rect::new(
+        (at_x + index as u32 * (BUTTON_WIDTH + MARGIN)) as i32,
+        at_y as i32,
+        BUTTON_WIDTH,
+        BUTTON_HEIGHT,
+    )
+}
+
+pub fn render_controls(
+    at_x: u32,
+    at_y: u32,
+    canvas: &mut sdl2::render::Canvas<sdl2::video::Window>,
+    font: &sdl2::ttf::Font<'_, '_>,
+) -> Result<(), String> {
+    let paused = pinmame::is_paused();
+    for (index, control) in CONTROLS.iter().enumerate() {
+        let rect = button_rect(at_x, at_y, index);
+        canvas.set_draw_color(Color::RGB(40, 40, 50));
+        canvas.fill_rect(rect)?;
+        canvas.set_draw_color(Color::RGB(120, 120, 140));
+        canvas.draw_rect(rect)?;
+        let label = match control {
+            Control::PauseResume if paused => "Resume",
+            Control::PauseResume => "Pause",
+            Control::Step => "Step",
+            Control::Reset => "Reset",
+        };
+        render_text(
+            rect.x() + 4,
+            rect.y() + 1,
+            label,
+            Color::RGB(200, 200, 200),
+            canvas,
+            font,
+        )?;
+    }
+    Ok(())
+}
+
+// The control under the mouse, relative to the top left of the buttons
+pub fn control_for_mouse(x: i32, y: i32) -> Option<Control> {
+    CONTROLS
+        .iter()
+        .enumerate()
+        .find(|(index, _)| button_rect(0, 0, *index).contains_point((x, y)))
+        .map(|(_, control)| *control)
+}
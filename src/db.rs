@@ -1,162 +1,381 @@
+use std::path::{Path, PathBuf};
+
 use indexmap::IndexMap;
+use serde::{Deserialize, Serialize};
+
+use crate::pinmame::{MechConfig, MechFlag};
+
+pub type NameIndex = IndexMap<u32, String>;
+pub type SwitchIndex = NameIndex;
+
+// Machine definitions live in toml files named after the rom, eg `t2_l8.toml`.
+// Definitions in the user directory take precedence over the ones we ship, which are read
+// relative to the working directory like the other shipped resources.
+const SHIPPED_MACHINES_DIR: &str = "res/machines";
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Machine {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub switches: Vec<Named>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub lamps: Vec<Named>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub coils: Vec<Named>,
+    // flashers are driven by solenoid outputs, they share the numbering with the coils
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub flashers: Vec<Named>,
+    // general illumination strings, numbered like pinmame does starting at 0
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub gi: Vec<Named>,
+    #[serde(default, skip_serializing_if = "Startup::is_empty")]
+    pub startup: Startup,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trough: Option<Trough>,
+    // custom mechs, pinmame handles the mechanics itself when there are none
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub mechs: Vec<Mech>,
+    // named locations in the nvram, eg audits and high scores
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub nvram_fields: Vec<NvramField>,
+    // key bindings from res/keys, the `[keys]` table of the definition overrides these
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_profile: Option<String>,
+}
+
+// Switch states the rom expects at boot, without these we get "balls missing" or
+// "check switches" errors.
+#[derive(Debug, Default, Deserialize, Serialize)]
+pub struct Startup {
+    // eg the coin door and the balls in the trough
+    #[serde(default)]
+    pub closed_switches: Vec<u32>,
+}
+
+impl Startup {
+    pub fn is_empty(&self) -> bool {
+        self.closed_switches.is_empty()
+    }
+}
+
+// Drives the simulated ball trough, see trough.rs
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Trough {
+    // trough switches, starting with the one next to the eject coil
+    pub switches: Vec<u32>,
+    pub eject_coil: u32,
+    // balls go straight to the playfield when there is no shooter lane switch
+    pub shooter_switch: Option<u32>,
+    // auto launch, otherwise the ball is launched by hand
+    pub launch_coil: Option<u32>,
+    // drained balls wait here until the outhole coil kicks them into the trough, games
+    // without an outhole drain straight into the trough
+    pub outhole_switch: Option<u32>,
+    pub outhole_coil: Option<u32>,
+    // defaults to one ball per trough switch
+    pub balls: Option<u32>,
+}
+
+// Name lookups by number for everything the tester renders
+pub struct Names {
+    pub switches: SwitchIndex,
+    pub lamps: NameIndex,
+    pub solenoids: NameIndex,
+    pub gi: NameIndex,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Named {
+    pub number: u32,
+    pub name: String,
+}
+
+impl Machine {
+    pub fn names(&self) -> Names {
+        Names {
+            switches: name_index(&self.switches),
+            lamps: name_index(&self.lamps),
+            solenoids: name_index(self.coils.iter().chain(&self.flashers)),
+            gi: name_index(&self.gi),
+        }
+    }
+}
+
+fn name_index<'a>(items: impl IntoIterator<Item = &'a Named>) -> NameIndex {
+    items
+        .into_iter()
+        .map(|item| (item.number, item.name.clone()))
+        .collect()
+}
+
+pub struct LoadedMachine {
+    pub path: PathBuf,
+    pub machine: Machine,
+}
+
+pub fn machine_dirs(pinmame_path: &Path) -> Vec<PathBuf> {
+    vec![
+        pinmame_path.join("tester").join("machines"),
+        PathBuf::from(SHIPPED_MACHINES_DIR),
+    ]
+}
+
+// Looks up the definition for a rom, falling back to its parent (`Game.clone_of`).
+pub fn load_machine(
+    dirs: &[PathBuf],
+    rom: &str,
+    clone_of: Option<&str>,
+) -> Result<Option<LoadedMachine>, String> {
+    let names = std::iter::once(rom).chain(clone_of.filter(|parent| !parent.is_empty()));
+    for name in names {
+        for dir in dirs {
+            let path = dir.join(format!("{}.toml", name));
+            if path.is_file() {
+                let machine = load_machine_file(&path)?;
+                return Ok(Some(LoadedMachine { path, machine }));
+            }
+        }
+    }
+    Ok(None)
+}
 
-pub type SwitchIndex = IndexMap<u32, &'static str>;
-
-lazy_static! {
-// from https://github.com/neophob/wpc-emu
-
-
-    pub static ref MM_SWITCHES: SwitchIndex = {
-        let mut m = IndexMap::new();
-
-        m.insert(1, "COIN#1" );
-        m.insert(2, "COIN#2" );
-        m.insert(3, "COIN#3" );
-        m.insert(4, "COIN#4" );
-        m.insert(5, "ESCAPE" );
-        m.insert(6, "-" );
-        m.insert(7, "+" );
-        m.insert(8, "ENTER" );
-        m.insert(9, "?" );
-        m.insert(10, "MENU / ENTER?" );
-
-        m.insert(11, "LAUNCH BUTTON");
-        m.insert(12, "CATAPULT TARGET");
-        m.insert(13, "START BUTTON");
-        m.insert(14, "PLUMB BOB TILT");
-        m.insert(15, "L TROLL TARGET");
-        m.insert(16, "LEFT OUTLANE");
-        m.insert(17, "RIGHT RETURN");
-        m.insert(18, "SHOOTER LANE");
-
-        m.insert(21, "SLAM TILT");
-        m.insert(22, "COIN DOOR CLOSED");
-        m.insert(25, "R TROLL TARGET");
-        m.insert(26, "LEFT RETURN");
-        m.insert(27, "RIGHT OUTLANE");
-        m.insert(28, "RIGHT EJECT");
-
-        m.insert(31, "TROUGH EJECT");
-        m.insert(32, "TROUGH BALL 1");
-        m.insert(33, "TROUGH BALL 2");
-        m.insert(34, "TROUGH BALL 3");
-        m.insert(35, "TROUGH BALL 4");
-        m.insert(36, "LEFT POPPER");
-        m.insert(37, "CASTLE GATE");
-        m.insert(38, "CATAPULT");
-
-        m.insert(41, "MOAT ENTER");
-        m.insert(44, "CASTLE LOCK");
-        m.insert(45, "L TROLL (U/PLDF)");
-        m.insert(46, "R TROLL (U/PLDF)");
-        m.insert(47, "LEFT TOP LANE");
-        m.insert(48, "RIGHT TOP LANE");
-
-        m.insert(51, "LEFT SLINGSHOT");
-        m.insert(52, "RIGHT SLINGSHOT");
-        m.insert(53, "LEFT JET");
-        m.insert(54, "BOTTOM JET");
-        m.insert(55, "RIGHT JET");
-        m.insert(56, "DRAWBRIDGE UP");
-        m.insert(57, "DRAWBRIDGE DOWN");
-        m.insert(58, "TOWER EXIT");
-
-        m.insert(61, "L RAMP ENTER");
-        m.insert(62, "L RAMP EXIT");
-        m.insert(63, "R RAMP ENTER");
-        m.insert(64, "R RAMP EXIT");
-        m.insert(65, "LEFT LOOP LO");
-        m.insert(66, "LEFT LOOP HI");
-        m.insert(67, "RIGHT LOOP LO");
-        m.insert(68, "RIGHT LOOP HI");
-
-        m.insert(71, "RIGHT BANK TOP");
-        m.insert(72, "RIGHT BANK MID");
-        m.insert(73, "RIGHT BANK BOT");
-        m.insert(74, "L TROLL UP");
-        m.insert(75, "R TROLL UP");
-
-        m
+pub fn load_machine_file(path: &Path) -> Result<Machine, String> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+    toml::from_str(&content).map_err(|e| format!("Could not parse {}: {}", path.display(), e))
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct NvramField {
+    pub name: String,
+    pub address: u32,
+    #[serde(default = "default_field_length")]
+    pub length: u32,
+    #[serde(default)]
+    pub encoding: NvramEncoding,
+}
+
+// How the bytes of a field are turned into a value, see audits.rs
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NvramEncoding {
+    // unsigned, big endian like the 6809
+    #[default]
+    Int,
+    // two digits per byte, eg scores
+    Bcd,
+    // eg high score initials
+    Ascii,
+    Hex,
+}
+
+fn default_field_length() -> u32 {
+    1
+}
+
+// Adds the field to the user definition of the rom. A shipped definition is copied there
+// first. The field is appended to the file so comments in it survive, the file is only
+// rewritten when appending does not give a valid definition, eg for an inline array.
+pub fn add_nvram_field(
+    pinmame_path: &Path,
+    rom: &str,
+    loaded_from: Option<&Path>,
+    field: &NvramField,
+) -> Result<PathBuf, String> {
+    let path = machine_dirs(pinmame_path)[0].join(format!("{}.toml", rom));
+    let content = if path.is_file() {
+        std::fs::read_to_string(&path)
+            .map_err(|e| format!("Could not read {}: {}", path.display(), e))?
+    } else {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        match loaded_from {
+            Some(source) => std::fs::read_to_string(source)
+                .map_err(|e| format!("Could not read {}: {}", source.display(), e))?,
+            None => format!("name = \"{}\"\n", rom),
+        }
     };
+    let content = with_nvram_field(&content, field)
+        .map_err(|e| format!("Could not add the field to {}: {}", path.display(), e))?;
+    std::fs::write(&path, content)
+        .map_err(|e| format!("Could not write {}: {}", path.display(), e))?;
+    Ok(path)
+}
 
-    pub static ref T2_SWITCHES: SwitchIndex = {
-        let mut m = IndexMap::new();
-
-        m.insert(1, "COIN#1" );
-        m.insert(2, "COIN#2" );
-        m.insert(3, "COIN#3" );
-        m.insert(4, "?" );
-        m.insert(5, "ESCAPE" );
-        m.insert(6, "-" );
-        m.insert(7, "+" );
-        m.insert(8, "ENTER" );
-        m.insert(9, "?" );
-        m.insert(10, "MENU / ENTER?" );
-
-        m.insert(11, "RIGHT FLIPPER");
-        m.insert(12, "LEFT FLIPPER");
-        m.insert(13, "START BUTTON");
-        m.insert(14, "PLUMB BOB TILT");
-        m.insert(15, "TROUGH LEFT");
-        m.insert(16, "TROUGH CENTER");
-        m.insert(17, "TROUGH RIGHT");
-        m.insert(18, "OUTHOLE");
-
-        m.insert(21, "SLAM TILT");
-        m.insert(22, "COIN DOOR CLOSED");
-        m.insert(23, "TICKED OPTQ");
-        m.insert(25, "LEFT OUT LANE");
-        m.insert(26, "LEFT RET. LANE");
-        m.insert(27, "RIGHT RET. LANE");
-        m.insert(28, "RIGHT OUT LANE");
-
-        m.insert(31, "GUN LOADED");
-        m.insert(32, "GUN MARK");
-        m.insert(33, "GUN HOME");
-        m.insert(34, "GRIP TRIGGER");
-        m.insert(36, "STAND MID LEFT");
-        m.insert(37, "STAND MID CENTER");
-        m.insert(38, "STAND MID RIGHT");
-
-        m.insert(41, "LEFT JET");
-        m.insert(42, "RIGHT JET");
-        m.insert(43, "BOTTOM JET");
-        m.insert(44, "LEFT SLING");
-        m.insert(45, "RIGHT SLING");
-        m.insert(46, "STAND RIGHT TOP");
-        m.insert(47, "STAND RIGHT MID");
-        m.insert(48, "STAND RIGHT BOT");
-
-        m.insert(51, "LEFT LOCK");
-        m.insert(53, "LO ESCAPE ROUTE");
-        m.insert(54, "HI ESCAPE ROUTE");
-        m.insert(55, "TOP LOCK");
-        m.insert(56, "TOP LANE LEFT");
-        m.insert(57, "TOP LANE CENTER");
-        m.insert(58, "TOP LANE RIGHT");
-
-        m.insert(61, "LEFT RAMP ENTRY");
-        m.insert(62, "LEFT RAMP MADE");
-        m.insert(63, "RIGHT RAMP ENTRY");
-        m.insert(64, "RIGHT RAMP MADE");
-        m.insert(65, "LO CHASE LOOP");
-        m.insert(66, "HI CHASE LOOP");
-
-        m.insert(71, "TARGET 1 HI");
-        m.insert(72, "TARGET 2");
-        m.insert(73, "TARGET 3");
-        m.insert(74, "TARGET 4");
-        m.insert(75, "TARGET 5 LOW");
-        m.insert(76, "BALL POPPER");
-        m.insert(77, "DROP TARGET");
-        m.insert(78, "SHOOTER");
-
-        m
+fn with_nvram_field(content: &str, field: &NvramField) -> Result<String, String> {
+    let fields = toml::from_str::<Machine>(content)
+        .map_err(|e| e.to_string())?
+        .nvram_fields
+        .len();
+    let is_added = |content: &str| {
+        toml::from_str::<Machine>(content)
+            .is_ok_and(|machine| machine.nvram_fields.len() == fields + 1)
     };
 
-    // pub static ref TABLES: HashMap<&'static str, SwitchIndex> = {
-    //     let mut m = HashMap::new();
-    //     m.insert("t2", T2_SWITCHES);
-    //     m
-    // };
+    #[derive(Serialize)]
+    struct Fields<'a> {
+        nvram_fields: [&'a NvramField; 1],
+    }
+    let toml = toml::to_string(&Fields {
+        nvram_fields: [field],
+    })
+    .map_err(|e| e.to_string())?;
+    let mut appended = content.to_string();
+    if !appended.is_empty() && !appended.ends_with('\n') {
+        appended.push('\n');
+    }
+    appended.push('\n');
+    appended.push_str(&toml);
+    if is_added(&appended) {
+        return Ok(appended);
+    }
+
+    let mut table: toml::Table = toml::from_str(content).map_err(|e| e.to_string())?;
+    let field = toml::Value::try_from(field).map_err(|e| e.to_string())?;
+    match table
+        .entry("nvram_fields")
+        .or_insert_with(|| toml::Value::Array(vec![]))
+    {
+        toml::Value::Array(fields) => fields.push(field),
+        _ => return Err("nvram_fields is not an array".to_string()),
+    }
+    let rewritten = toml::to_string(&table).map_err(|e| e.to_string())?;
+    if is_added(&rewritten) {
+        Ok(rewritten)
+    } else {
+        Err("the result is not a valid definition".to_string())
+    }
+}
+
+// See PinmameMechConfig, the flags are combined into its type
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Mech {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub flags: Vec<MechFlag>,
+    pub sol1: u32,
+    #[serde(default)]
+    pub sol2: u32,
+    pub length: u32,
+    pub steps: u32,
+    #[serde(default)]
+    pub initial_pos: u32,
+    #[serde(default)]
+    pub acc: u32,
+    #[serde(default)]
+    pub ret: u32,
+    #[serde(default)]
+    pub switches: Vec<MechSwitch>,
+}
+
+impl TryFrom<&Mech> for MechConfig {
+    type Error = String;
+
+    fn try_from(mech: &Mech) -> Result<MechConfig, String> {
+        let mut builder =
+            MechConfig::builder(mech.sol1 as i32, mech.length as i32, mech.steps as i32)
+                .sol2(mech.sol2 as i32)
+                .initial_pos(mech.initial_pos as i32)
+                .acc(mech.acc as i32)
+                .ret(mech.ret as i32);
+        for flag in &mech.flags {
+            builder = builder.flag(*flag);
+        }
+        for switch in &mech.switches {
+            builder = builder.switch(
+                switch.switch as i32,
+                switch.start as i32,
+                switch.end as i32,
+                switch.pulse as i32,
+            );
+        }
+        builder.build()
+    }
+}
+
+// The switch is closed while the mech is between start and end
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MechSwitch {
+    pub switch: u32,
+    pub start: u32,
+    pub end: u32,
+    #[serde(default)]
+    pub pulse: u32,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shipped_machines_parse() {
+        for rom in ["t2_l8", "mm_109"] {
+            let path = Path::new(SHIPPED_MACHINES_DIR).join(format!("{}.toml", rom));
+            let machine = load_machine_file(&path).unwrap();
+            let trough = machine.trough.unwrap();
+            assert!(!trough.switches.is_empty(), "{} has an empty trough", rom);
+        }
+    }
+
+    #[test]
+    fn shipped_mechs_convert_to_configs() {
+        let path = Path::new(SHIPPED_MACHINES_DIR).join("t2_l8.toml");
+        let machine = load_machine_file(&path).unwrap();
+        assert!(!machine.mechs.is_empty());
+        for mech in &machine.mechs {
+            MechConfig::try_from(mech).unwrap();
+        }
+        let mech = Mech {
+            steps: 0,
+            ..machine.mechs[0].clone()
+        };
+        assert!(MechConfig::try_from(&mech).is_err());
+    }
+
+    fn field(name: &str, address: u32) -> NvramField {
+        NvramField {
+            name: name.to_string(),
+            address,
+            length: 2,
+            encoding: NvramEncoding::Bcd,
+        }
+    }
+
+    #[test]
+    fn appends_nvram_field_keeping_comments() {
+        let content =
+            "# my notes\nname = \"t2_l8\"\n\n[[nvram_fields]]\nname = \"credits\"\naddress = 16";
+        let added = with_nvram_field(content, &field("games", 32)).unwrap();
+        assert!(added.starts_with(content));
+        let machine: Machine = toml::from_str(&added).unwrap();
+        let names: Vec<&str> = machine
+            .nvram_fields
+            .iter()
+            .map(|f| f.name.as_str())
+            .collect();
+        assert_eq!(names, ["credits", "games"]);
+        assert_eq!(machine.nvram_fields[1].address, 32);
+        assert_eq!(machine.nvram_fields[1].encoding, NvramEncoding::Bcd);
+    }
+
+    #[test]
+    fn adds_nvram_field_to_inline_array() {
+        let content = "name = \"t2_l8\"\nnvram_fields = [{ name = \"credits\", address = 16 }]\n";
+        let added = with_nvram_field(content, &field("games", 32)).unwrap();
+        let machine: Machine = toml::from_str(&added).unwrap();
+        assert_eq!(machine.name, "t2_l8");
+        let names: Vec<&str> = machine
+            .nvram_fields
+            .iter()
+            .map(|f| f.name.as_str())
+            .collect();
+        assert_eq!(names, ["credits", "games"]);
+    }
+
+    #[test]
+    fn refuses_invalid_definition() {
+        assert!(with_nvram_field("name = ", &field("games", 32)).is_err());
+    }
 }
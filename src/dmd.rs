@@ -1,8 +1,10 @@
 use log::warn;
 use sdl2::{pixels, rect::Rect};
 
-use crate::libpinmame::{
-    PINMAME_DISPLAY_TYPE_PINMAME_DISPLAY_TYPE_SEG16S, PinmameDisplayLayout, PinmameMechInfo,
+use crate::{
+    db::NameIndex,
+    libpinmame::{PINMAME_DISPLAY_TYPE_PINMAME_DISPLAY_TYPE_SEG16S, PinmameDisplayLayout},
+    text::render_text,
 };
 
 const PIXEL_SIZE: u32 = 3;
@@ -200,96 +202,253 @@ pub(crate) fn dump_alphanumeric(
     }
 }
 
-const MECH_BAR_MULTIPLIER: u32 = 2;
-pub fn render_mechs(
+// the lamp and solenoid panels have a title above their grid
+pub const PANEL_TITLE_HEIGHT: u32 = 14;
+const GRID_COLUMNS: u32 = 20;
+const GRID_ROWS: u32 = 10;
+
+pub fn render_lights(
     at_x: u32,
     at_y: u32,
-    mech_info: &[PinmameMechInfo],
+    lamps: &[bool],
+    names: &NameIndex,
     canvas: &mut sdl2::render::Canvas<sdl2::video::Window>,
+    font: &sdl2::ttf::Font<'_, '_>,
+    lamp_size: u32,
 ) -> Result<(), String> {
-    let mech_bar_height = 10;
-    for (pos, mech) in mech_info.iter().enumerate() {
-        let color = pixels::Color::RGB(150, 250, 150);
-        let fill_color = pixels::Color::RGB(50, 150, 50);
-        canvas.set_draw_color(fill_color);
-        canvas.fill_rect(Rect::new(
-            at_x as i32,
-            at_y as i32 + pos as i32 * mech_bar_height as i32,
-            mech.pos as u32 * MECH_BAR_MULTIPLIER,
-            mech_bar_height,
-        ))?;
-        canvas.set_draw_color(color);
-        canvas.draw_rect(Rect::new(
-            at_x as i32,
-            at_y as i32 + pos as i32 * mech_bar_height as i32,
-            mech.length as u32 * MECH_BAR_MULTIPLIER,
-            mech_bar_height,
-        ))?;
-    }
-    Ok(())
+    render_text(
+        at_x as i32,
+        at_y as i32,
+        "Lamps",
+        pixels::Color::RGB(150, 150, 150),
+        canvas,
+        font,
+    )?;
+    render_grid(
+        at_x,
+        at_y + PANEL_TITLE_HEIGHT,
+        lamps,
+        names,
+        canvas,
+        lamp_size,
+    )
 }
 
-pub fn render_lights(
+pub(crate) fn render_solenoids(
     at_x: u32,
     at_y: u32,
-    lamps: &[bool],
+    solenoids: &[bool],
+    names: &NameIndex,
     canvas: &mut sdl2::render::Canvas<sdl2::video::Window>,
-    lamp_size: u32,
+    font: &sdl2::ttf::Font<'_, '_>,
+    solenoid_size: u32,
 ) -> Result<(), String> {
-    // lights are numberedred from 1
-    let mut lamp_index = 1;
-    for x in 0..20 {
-        for y in 0..10 {
-            if lamp_index >= lamps.len() {
+    render_text(
+        at_x as i32,
+        at_y as i32,
+        "Solenoids",
+        pixels::Color::RGB(150, 150, 150),
+        canvas,
+        font,
+    )?;
+    render_grid(
+        at_x,
+        at_y + PANEL_TITLE_HEIGHT,
+        solenoids,
+        names,
+        canvas,
+        solenoid_size,
+    )
+}
+
+fn render_grid(
+    at_x: u32,
+    at_y: u32,
+    states: &[bool],
+    names: &NameIndex,
+    canvas: &mut sdl2::render::Canvas<sdl2::video::Window>,
+    size: u32,
+) -> Result<(), String> {
+    // lamps and solenoids are numbered from 1
+    let mut number = 1;
+    for x in 0..GRID_COLUMNS {
+        for y in 0..GRID_ROWS {
+            if number >= states.len() {
                 break;
             }
-            let color = if lamps[lamp_index] {
+            let color = if states[number] {
                 pixels::Color::RGB(255, 255, 100)
             } else {
                 pixels::Color::RGB(20, 20, 10)
             };
+            let rect = Rect::new(
+                (at_x + x * (size + 1)) as i32,
+                (at_y + y * (size + 1)) as i32,
+                size,
+                size,
+            );
             canvas.set_draw_color(color);
-            canvas.fill_rect(Rect::new(
-                (at_x + x * (lamp_size + 1)) as i32,
-                (at_y + y * (lamp_size + 1)) as i32,
-                lamp_size,
-                lamp_size,
-            ))?;
-            lamp_index += 1;
+            canvas.fill_rect(rect)?;
+            // mark the ones we know the name of
+            if names.contains_key(&(number as u32)) {
+                canvas.set_draw_color(pixels::Color::RGB(120, 120, 60));
+                canvas.draw_rect(rect)?;
+            }
+            number += 1;
         }
     }
     Ok(())
 }
 
-pub(crate) fn render_solenoids(
+const LED_WIDTH: i32 = 10;
+const LED_HEIGHT: i32 = 16;
+const LED_SPACING: u32 = 16;
+const LEDS_PER_ROW: u32 = 16;
+const LED_ROW_HEIGHT: u32 = LED_HEIGHT as u32 + 6;
+
+// Renders the leds as segment digits like the PinMAME cores lay them out:
+// bit 0-5 the outer segments a-f, bit 6 the left and bit 11 the right half of the middle,
+// bit 8-10 and 12-14 the diagonals and verticals of 14/16 segment displays,
+// bit 7 the comma and bit 15 the period.
+pub fn render_leds(
     at_x: u32,
     at_y: u32,
-    solenoids: &[bool],
+    leds: &[i32],
     canvas: &mut sdl2::render::Canvas<sdl2::video::Window>,
-    solenoid_size: u32,
+    font: &sdl2::ttf::Font<'_, '_>,
 ) -> Result<(), String> {
-    // solenoids are numberedred from 1
-    let mut solenoid_index = 1;
-    for x in 0..20 {
-        for y in 0..10 {
-            if solenoid_index >= solenoids.len() {
-                break;
-            }
-
-            let color = if solenoids[solenoid_index] {
-                pixels::Color::RGB(255, 255, 100)
+    render_text(
+        at_x as i32,
+        at_y as i32,
+        "LEDs",
+        pixels::Color::RGB(150, 150, 150),
+        canvas,
+        font,
+    )?;
+    let (w, h) = (LED_WIDTH, LED_HEIGHT);
+    let half = h / 2;
+    let center = w / 2;
+    for (led_no, state) in leds.iter().enumerate() {
+        let x = (at_x + (led_no as u32 % LEDS_PER_ROW) * LED_SPACING) as i32;
+        let y =
+            (at_y + PANEL_TITLE_HEIGHT + (led_no as u32 / LEDS_PER_ROW) * LED_ROW_HEIGHT) as i32;
+        // 7 segment digits only use bit 6 for the whole middle segment
+        let alphanumeric = state & 0x7f00 != 0;
+        let middle_right = if alphanumeric { 11 } else { 6 };
+        let bars = [
+            (0, Rect::new(x + 1, y, (w - 2) as u32, 1)),
+            (1, Rect::new(x + w - 1, y + 1, 1, (half - 1) as u32)),
+            (2, Rect::new(x + w - 1, y + half + 1, 1, (half - 1) as u32)),
+            (3, Rect::new(x + 1, y + h, (w - 2) as u32, 1)),
+            (4, Rect::new(x, y + half + 1, 1, (half - 1) as u32)),
+            (5, Rect::new(x, y + 1, 1, (half - 1) as u32)),
+            (6, Rect::new(x + 1, y + half, (center - 1) as u32, 1)),
+            (
+                middle_right,
+                Rect::new(x + center, y + half, (center - 1) as u32, 1),
+            ),
+            (9, Rect::new(x + center, y + 2, 1, (half - 3) as u32)),
+            (
+                13,
+                Rect::new(x + center, y + half + 2, 1, (half - 3) as u32),
+            ),
+            (7, Rect::new(x + w + 1, y + h, 1, 3)),
+            (15, Rect::new(x + w + 1, y + h, 1, 1)),
+        ];
+        let diagonals = [
+            (8, (x + 2, y + 2), (x + center - 2, y + half - 2)),
+            (10, (x + w - 3, y + 2), (x + center + 1, y + half - 2)),
+            (12, (x + center + 1, y + half + 2), (x + w - 3, y + h - 2)),
+            (14, (x + center - 2, y + half + 2), (x + 2, y + h - 2)),
+        ];
+        // the lit segments go last as some of them overlap
+        for lit in [false, true] {
+            let color = if lit {
+                pixels::Color::RGB(255, 60, 30)
             } else {
-                pixels::Color::RGB(20, 20, 10)
+                pixels::Color::RGB(40, 15, 10)
             };
             canvas.set_draw_color(color);
-            canvas.fill_rect(Rect::new(
-                (at_x + x * (solenoid_size + 1)) as i32,
-                (at_y + y * (solenoid_size + 1)) as i32,
-                solenoid_size,
-                solenoid_size,
-            ))?;
-            solenoid_index += 1;
+            for (bit, bar) in &bars {
+                if (state & (1 << bit) != 0) == lit {
+                    canvas.fill_rect(*bar)?;
+                }
+            }
+            for (bit, from, to) in &diagonals {
+                if (state & (1 << bit) != 0) == lit {
+                    canvas.draw_line(*from, *to)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+// WPC dims the general illumination in 8 steps
+pub const GI_MAX_LEVEL: i32 = 8;
+const GI_SIZE: u32 = 12;
+const GI_ROW_HEIGHT: u32 = 16;
+
+pub fn render_gi(
+    at_x: u32,
+    at_y: u32,
+    gi: &[i32],
+    names: &NameIndex,
+    canvas: &mut sdl2::render::Canvas<sdl2::video::Window>,
+    font: &sdl2::ttf::Font<'_, '_>,
+) -> Result<(), String> {
+    render_text(
+        at_x as i32,
+        at_y as i32,
+        "GI",
+        pixels::Color::RGB(150, 150, 150),
+        canvas,
+        font,
+    )?;
+    for (gi_no, level) in gi.iter().enumerate() {
+        let y = at_y + PANEL_TITLE_HEIGHT + gi_no as u32 * GI_ROW_HEIGHT;
+        let brightness = (*level).clamp(0, GI_MAX_LEVEL) as u32;
+        let scale = |max: u32| (20 + (max - 20) * brightness / GI_MAX_LEVEL as u32) as u8;
+        let rect = Rect::new(at_x as i32, y as i32, GI_SIZE, GI_SIZE);
+        canvas.set_draw_color(pixels::Color::RGB(scale(255), scale(220), scale(150)));
+        canvas.fill_rect(rect)?;
+        if names.contains_key(&(gi_no as u32)) {
+            canvas.set_draw_color(pixels::Color::RGB(120, 120, 60));
+            canvas.draw_rect(rect)?;
         }
+        render_text(
+            (at_x + GI_SIZE + 4) as i32,
+            y as i32 - 1,
+            &format!("{} {}/{}", gi_no, brightness, GI_MAX_LEVEL),
+            pixels::Color::RGB(150, 150, 150),
+            canvas,
+            font,
+        )?;
     }
     Ok(())
 }
+
+// Returns the GI string under the mouse, x and y are relative to the panel
+pub fn gi_number_for_mouse(x: i32, y: i32, count: usize) -> Option<usize> {
+    let y = y - PANEL_TITLE_HEIGHT as i32;
+    if !(0..GI_SIZE as i32).contains(&x) || y < 0 {
+        return None;
+    }
+    let gi_no = (y as u32 / GI_ROW_HEIGHT) as usize;
+    (gi_no < count).then_some(gi_no)
+}
+
+// Returns the lamp or solenoid number under the mouse, x and y are relative to the panel
+pub fn grid_number_for_mouse(x: i32, y: i32, size: u32, count: usize) -> Option<usize> {
+    let y = y - PANEL_TITLE_HEIGHT as i32;
+    if x < 0 || y < 0 {
+        return None;
+    }
+    let column = x as u32 / (size + 1);
+    let row = y as u32 / (size + 1);
+    if column >= GRID_COLUMNS || row >= GRID_ROWS {
+        return None;
+    }
+    let number = (column * GRID_ROWS + row + 1) as usize;
+    (number < count).then_some(number)
+}
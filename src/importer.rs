@@ -0,0 +1,387 @@
+use std::path::Path;
+
+use log::{info, warn};
+use serde::Deserialize;
+
+use crate::{
+    cli::{ImportArgs, ImportFormat},
+    db::{Machine, Mech, MechSwitch, Named, Startup},
+    pinmame::MechFlag,
+};
+
+pub fn import(args: &ImportArgs) -> Result<(), String> {
+    let source = std::fs::read_to_string(&args.input)
+        .map_err(|e| format!("Could not read {}: {}", args.input.display(), e))?;
+    let machine = match args.format {
+        ImportFormat::WpcEmu => import_wpc_emu(&source)?,
+        ImportFormat::Vpe => import_vpe(&source, &args.input),
+    };
+    info!(
+        "Imported \"{}\": {} switches, {} lamps, {} coils, {} mechs",
+        machine.name,
+        machine.switches.len(),
+        machine.lamps.len(),
+        machine.coils.len(),
+        machine.mechs.len()
+    );
+    let toml = toml::to_string(&machine).map_err(|e| e.to_string())?;
+    match &args.output {
+        Some(output) => std::fs::write(output, toml)
+            .map_err(|e| format!("Could not write {}: {}", output.display(), e)),
+        None => {
+            print!("{}", toml);
+            Ok(())
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct WpcEmuGame {
+    name: String,
+    #[serde(default)]
+    switch_mapping: Vec<WpcEmuMapping>,
+    #[serde(default)]
+    lamp_mapping: Vec<WpcEmuMapping>,
+    #[serde(default)]
+    solenoid_mapping: Vec<WpcEmuMapping>,
+    #[serde(default)]
+    mechs: Vec<WpcEmuMech>,
+    #[serde(default)]
+    initialise: WpcEmuInitialise,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+struct WpcEmuInitialise {
+    #[serde(default)]
+    closed_switches: Vec<u32>,
+}
+
+#[derive(Deserialize)]
+struct WpcEmuMapping {
+    id: u32,
+    name: String,
+}
+
+impl From<WpcEmuMapping> for Named {
+    fn from(mapping: WpcEmuMapping) -> Named {
+        Named {
+            number: mapping.id,
+            name: mapping.name,
+        }
+    }
+}
+
+// wpc-emu has no mech descriptions of its own, these use the fields of our `[[mechs]]` in
+// camel case
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct WpcEmuMech {
+    name: Option<String>,
+    #[serde(default)]
+    flags: Vec<MechFlag>,
+    sol1: u32,
+    #[serde(default)]
+    sol2: u32,
+    length: u32,
+    steps: u32,
+    #[serde(default)]
+    initial_pos: u32,
+    #[serde(default)]
+    acc: u32,
+    #[serde(default)]
+    ret: u32,
+    #[serde(default)]
+    switches: Vec<MechSwitch>,
+}
+
+impl From<WpcEmuMech> for Mech {
+    fn from(mech: WpcEmuMech) -> Mech {
+        Mech {
+            name: mech.name,
+            flags: mech.flags,
+            sol1: mech.sol1,
+            sol2: mech.sol2,
+            length: mech.length,
+            steps: mech.steps,
+            initial_pos: mech.initial_pos,
+            acc: mech.acc,
+            ret: mech.ret,
+            switches: mech.switches,
+        }
+    }
+}
+
+// wpc-emu keeps its game definitions in javascript modules (lib/db/*.js), we expect
+// the exported object saved as json. Lamps and solenoids are read from `lampMapping` and
+// `solenoidMapping` lists shaped like `switchMapping`.
+fn import_wpc_emu(source: &str) -> Result<Machine, String> {
+    let game: WpcEmuGame = serde_json::from_str(source).map_err(|e| e.to_string())?;
+    Ok(Machine {
+        name: game.name,
+        switches: game.switch_mapping.into_iter().map(Named::from).collect(),
+        lamps: game.lamp_mapping.into_iter().map(Named::from).collect(),
+        coils: game.solenoid_mapping.into_iter().map(Named::from).collect(),
+        mechs: game.mechs.into_iter().map(Mech::from).collect(),
+        startup: Startup {
+            closed_switches: game.initialise.closed_switches,
+        },
+        ..Default::default()
+    })
+}
+
+// VPE describes the games in C# classes like
+// `new GamelogicEngineSwitch("11") { Description = "Right Flipper", ... }`
+// we don't try to parse C#, we just look for these constructors.
+fn import_vpe(source: &str, path: &Path) -> Machine {
+    let name = class_name(source)
+        .or_else(|| path.file_stem().map(|s| s.to_string_lossy().to_string()))
+        .unwrap_or_default();
+    Machine {
+        name,
+        switches: parse_vpe_items(source, "GamelogicEngineSwitch"),
+        lamps: parse_vpe_items(source, "GamelogicEngineLamp"),
+        coils: parse_vpe_items(source, "GamelogicEngineCoil"),
+        mechs: parse_vpe_mechs(source),
+        ..Default::default()
+    }
+}
+
+// Mechs are set up like in pinmame-dotnet
+//
+//   var mech = new PinMameMechConfig(PinMameMechFlag.NonLinear | PinMameMechFlag.OneSol, 11, 240, 240);
+//   mech.AddSwitch(new PinMameMechSwitchConfig(32, 0, 5));
+//
+// the constructor arguments are read as type, sol1, length, steps, initialPos, acc, ret and
+// sol2, the switches as switch, start, end and pulse. Switches belong to the mech before them.
+fn parse_vpe_mechs(source: &str) -> Vec<Mech> {
+    const MECH: &str = "new PinMameMechConfig(";
+    const SWITCH: &str = "new PinMameMechSwitchConfig(";
+    let mut mechs = Vec::new();
+    let starts: Vec<usize> = source.match_indices(MECH).map(|(start, _)| start).collect();
+    for (index, start) in starts.iter().enumerate() {
+        let end = starts.get(index + 1).copied().unwrap_or(source.len());
+        let block = &source[start + MECH.len()..end];
+        let Some(arguments) = call_arguments(block) else {
+            continue;
+        };
+        let mut arguments = arguments.into_iter();
+        let flags = arguments
+            .next()
+            .map(|flags| parse_mech_flags(&flags))
+            .unwrap_or_default();
+        let numbers: Vec<u32> = arguments.map(|argument| number(&argument)).collect();
+        let at = |index: usize| numbers.get(index).copied().unwrap_or(0);
+        if numbers.len() < 3 {
+            warn!("Skipping PinMameMechConfig without sol1, length and steps");
+            continue;
+        }
+        let switches = block
+            .match_indices(SWITCH)
+            .filter_map(|(start, _)| call_arguments(&block[start + SWITCH.len()..]))
+            .map(|arguments| {
+                let numbers: Vec<u32> = arguments.iter().map(|argument| number(argument)).collect();
+                let at = |index: usize| numbers.get(index).copied().unwrap_or(0);
+                MechSwitch {
+                    switch: at(0),
+                    start: at(1),
+                    end: at(2),
+                    pulse: at(3),
+                }
+            })
+            .collect();
+        mechs.push(Mech {
+            name: None,
+            flags,
+            sol1: at(0),
+            length: at(1),
+            steps: at(2),
+            initial_pos: at(3),
+            acc: at(4),
+            ret: at(5),
+            sol2: at(6),
+            switches,
+        });
+    }
+    mechs
+}
+
+// The comma separated arguments up to the closing parenthesis
+fn call_arguments(rest: &str) -> Option<Vec<String>> {
+    let end = rest.find(')')?;
+    Some(
+        rest[..end]
+            .split(',')
+            .map(|argument| argument.trim().to_string())
+            .filter(|argument| !argument.is_empty())
+            .collect(),
+    )
+}
+
+fn number(argument: &str) -> u32 {
+    argument.parse().unwrap_or_else(|_| {
+        warn!("Using 0 for mech argument {}", argument);
+        0
+    })
+}
+
+// eg `PinMameMechFlag.NonLinear | PinMameMechFlag.OneSol`
+fn parse_mech_flags(flags: &str) -> Vec<MechFlag> {
+    flags
+        .split('|')
+        .filter_map(|flag| {
+            let name = flag.trim().rsplit('.').next()?.to_lowercase();
+            let parsed = serde_json::from_value(serde_json::Value::String(name)).ok();
+            if parsed.is_none() {
+                warn!("Unknown mech flag {}", flag.trim());
+            }
+            parsed
+        })
+        .collect()
+}
+
+fn class_name(source: &str) -> Option<String> {
+    let start = source.find("class ")? + "class ".len();
+    let name: String = source[start..]
+        .chars()
+        .take_while(|c| c.is_alphanumeric() || *c == '_')
+        .collect();
+    (!name.is_empty()).then_some(name)
+}
+
+fn parse_vpe_items(source: &str, type_name: &str) -> Vec<Named> {
+    let constructor = format!("new {}(", type_name);
+    let mut items = Vec::new();
+    for (start, _) in source.match_indices(&constructor) {
+        let rest = &source[start + constructor.len()..];
+        let Some(end) = rest.find(')') else {
+            continue;
+        };
+        let id = rest[..end].trim().trim_matches('"');
+        let Ok(number) = id.parse::<u32>() else {
+            warn!("Skipping {} with non numeric id {}", type_name, id);
+            continue;
+        };
+        let initializer = rest[end + 1..].trim_start();
+        let name = if initializer.starts_with('{') {
+            initializer_body(initializer).and_then(|body| string_property(body, "Description"))
+        } else {
+            None
+        };
+        items.push(Named {
+            number,
+            name: name.unwrap_or_else(|| "?".to_string()),
+        });
+    }
+    items
+}
+
+// the text between the braces of an object initializer, skipping over string literals
+fn initializer_body(initializer: &str) -> Option<&str> {
+    let mut depth = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (index, c) in initializer.char_indices() {
+        if in_string {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => in_string = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => in_string = true,
+            '{' => depth += 1,
+            '}' => {
+                depth -= 1;
+                if depth == 0 {
+                    return Some(&initializer[1..index]);
+                }
+            }
+            _ => {}
+        }
+    }
+    None
+}
+
+fn string_property(body: &str, property: &str) -> Option<String> {
+    let start = body.find(property)? + property.len();
+    let rest = body[start..].trim_start().strip_prefix('=')?.trim_start();
+    let rest = rest.strip_prefix('"')?;
+    let mut value = String::new();
+    let mut chars = rest.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => value.extend(chars.next()),
+            '"' => return Some(value),
+            _ => value.push(c),
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn imports_wpc_emu() {
+        let machine = import_wpc_emu(include_str!("../tests/fixtures/wpc_t2.json")).unwrap();
+        assert_eq!(machine.name, "WPC-DMD: Terminator 2");
+        assert_eq!(machine.switches.len(), 3);
+        assert_eq!(machine.switches[2].number, 13);
+        assert_eq!(machine.switches[2].name, "START BUTTON");
+        assert_eq!(machine.lamps.len(), 2);
+        assert_eq!(machine.lamps[0].name, "SUPER JACKPOT");
+        assert_eq!(machine.coils[1].number, 11);
+        assert_eq!(machine.coils[1].name, "GUN MOTOR");
+        assert_eq!(machine.startup.closed_switches, vec![22, 15, 16, 17]);
+
+        let gun = &machine.mechs[0];
+        assert_eq!(gun.name.as_deref(), Some("Gun"));
+        assert_eq!(
+            gun.flags,
+            vec![MechFlag::NonLinear, MechFlag::Reverse, MechFlag::OneSol]
+        );
+        assert_eq!((gun.sol1, gun.length, gun.steps), (11, 240, 240));
+        assert_eq!(gun.switches[0].switch, 32);
+        assert_eq!((gun.switches[0].start, gun.switches[0].end), (0, 5));
+    }
+
+    #[test]
+    fn imports_vpe() {
+        let machine = import_vpe(
+            include_str!("../tests/fixtures/Terminator2.cs"),
+            Path::new("Terminator2.cs"),
+        );
+        assert_eq!(machine.name, "Terminator2");
+        // the switch with a non numeric id is skipped
+        assert_eq!(machine.switches.len(), 2);
+        assert_eq!(machine.switches[0].name, "Right Flipper");
+        assert_eq!(machine.switches[1].name, "Left \"Flipper\"");
+        assert_eq!(machine.coils.len(), 2);
+        assert_eq!(machine.coils[0].number, 1);
+        assert_eq!(machine.lamps[0].name, "Super Jackpot");
+
+        assert_eq!(machine.mechs.len(), 1);
+        let gun = &machine.mechs[0];
+        assert_eq!(
+            gun.flags,
+            vec![MechFlag::NonLinear, MechFlag::Reverse, MechFlag::OneSol]
+        );
+        assert_eq!((gun.sol1, gun.length, gun.steps), (11, 240, 240));
+        assert_eq!(gun.switches.len(), 2);
+        assert_eq!(gun.switches[1].switch, 32);
+        assert_eq!(
+            (
+                gun.switches[1].start,
+                gun.switches[1].end,
+                gun.switches[1].pulse
+            ),
+            (98, 105, 2)
+        );
+    }
+}
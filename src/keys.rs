@@ -0,0 +1,245 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use log::info;
+use sdl2::{
+    controller::{Axis, Button},
+    keyboard::Keycode,
+};
+use serde::Deserialize;
+
+use crate::{keyboard::map_keycode, libpinmame::PINMAME_KEYCODE};
+
+// relative to the working directory, like the other shipped resources
+const SHIPPED_PROFILES_DIR: &str = "res/keys";
+// axes count as pressed past half way
+const AXIS_THRESHOLD: i32 = i16::MAX as i32 / 2;
+
+// Key bindings are toml files with a table of SDL key names, eg
+//
+//   [keys]
+//   "Left Shift" = { switch = 11 }
+//   "F2" = { pinmame = "F2" }
+//   "Pause" = { action = "pause" }
+//
+// From low to high priority bindings come from the profile the machine definition names, the
+// `[keys]` table of the machine definition, `~/.pinmame/tester/keys.toml` with the user bindings
+// and `~/.pinmame/tester/keys/<rom>.toml` for a single game. Keys that are not bound go to
+// pinmame as is.
+//
+// Game controllers are bound the same way with the SDL button and axis names. Sticks can be
+// bound per direction by adding `+` or `-` to the axis name.
+//
+//   [buttons]
+//   "leftshoulder" = { switch = 114 }
+//
+//   [axes]
+//   "righttrigger" = { switch = 11 }
+//   "leftx-" = { pinmame = "Left" }
+#[derive(Debug, Default, Deserialize)]
+struct KeysFile {
+    #[serde(default)]
+    keys: HashMap<String, BindingConfig>,
+    #[serde(default)]
+    buttons: HashMap<String, BindingConfig>,
+    #[serde(default)]
+    axes: HashMap<String, BindingConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum BindingConfig {
+    // the pinmame key with this SDL key name
+    Pinmame(String),
+    Switch(u32),
+    Action(TesterAction),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TesterAction {
+    Pause,
+    // runs a paused machine for a frame
+    Step,
+    Reset,
+    Screenshot,
+    // asks for confirmation first
+    Quit,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum KeyBinding {
+    Pinmame(PINMAME_KEYCODE),
+    Switch(u32),
+    Action(TesterAction),
+}
+
+struct AxisBinding {
+    axis: Axis,
+    // either direction when not set
+    positive: Option<bool>,
+    binding: KeyBinding,
+    pressed: bool,
+}
+
+#[derive(Default)]
+pub struct KeyBindings {
+    bindings: HashMap<Keycode, KeyBinding>,
+    buttons: HashMap<Button, KeyBinding>,
+    axes: Vec<AxisBinding>,
+}
+
+impl KeyBindings {
+    pub fn load(
+        pinmame_path: &Path,
+        rom: &str,
+        profile: Option<&str>,
+        machine_path: Option<&Path>,
+    ) -> Result<KeyBindings, String> {
+        let dir = pinmame_path.join("tester");
+        let mut bindings = KeyBindings::default();
+        if let Some(profile) = profile {
+            let path = [dir.join("profiles"), PathBuf::from(SHIPPED_PROFILES_DIR)]
+                .iter()
+                .map(|dir| dir.join(format!("{}.toml", profile)))
+                .find(|path| path.is_file())
+                .ok_or_else(|| format!("Key profile {} not found", profile))?;
+            info!("Using key profile {}", path.display());
+            bindings.add_file(&path)?;
+        }
+        // the machine definition is read for its bindings only
+        if let Some(path) = machine_path {
+            bindings.add_file(path)?;
+        }
+        for path in [
+            dir.join("keys.toml"),
+            dir.join("keys").join(format!("{}.toml", rom)),
+        ] {
+            if path.is_file() {
+                info!("Using key bindings from {}", path.display());
+                bindings.add_file(&path)?;
+            }
+        }
+        Ok(bindings)
+    }
+
+    pub fn add_file(&mut self, path: &Path) -> Result<(), String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+        let file: KeysFile = toml::from_str(&content)
+            .map_err(|e| format!("Could not parse {}: {}", path.display(), e))?;
+        let in_file = |e: String| format!("{}: {}", path.display(), e);
+        for (name, config) in file.keys {
+            let keycode = parse_key(&name).map_err(in_file)?;
+            self.bindings
+                .insert(keycode, resolve(config).map_err(in_file)?);
+        }
+        for (name, config) in file.buttons {
+            let button = Button::from_string(&name)
+                .ok_or_else(|| in_file(format!("Unknown button \"{}\"", name)))?;
+            self.buttons
+                .insert(button, resolve(config).map_err(in_file)?);
+        }
+        for (name, config) in file.axes {
+            let (axis_name, positive) = match name.strip_suffix('+') {
+                Some(axis_name) => (axis_name, Some(true)),
+                None => match name.strip_suffix('-') {
+                    Some(axis_name) => (axis_name, Some(false)),
+                    None => (name.as_str(), None),
+                },
+            };
+            let axis = Axis::from_string(axis_name)
+                .ok_or_else(|| in_file(format!("Unknown axis \"{}\"", name)))?;
+            let binding = resolve(config).map_err(in_file)?;
+            self.axes
+                .retain(|other| other.axis != axis || other.positive != positive);
+            self.axes.push(AxisBinding {
+                axis,
+                positive,
+                binding,
+                pressed: false,
+            });
+        }
+        Ok(())
+    }
+
+    pub fn button(&self, button: Button) -> Option<KeyBinding> {
+        self.buttons.get(&button).copied()
+    }
+
+    // The bindings that got pressed or released by the axis motion
+    pub fn axis_motion(&mut self, axis: Axis, value: i16) -> Vec<(KeyBinding, bool)> {
+        let value = value as i32;
+        let mut changes = Vec::new();
+        for binding in self.axes.iter_mut().filter(|binding| binding.axis == axis) {
+            let pressed = match binding.positive {
+                Some(true) => value > AXIS_THRESHOLD,
+                Some(false) => value < -AXIS_THRESHOLD,
+                None => value.abs() > AXIS_THRESHOLD,
+            };
+            if pressed != binding.pressed {
+                binding.pressed = pressed;
+                changes.push((binding.binding, pressed));
+            }
+        }
+        changes
+    }
+
+    // Falls back to the default pinmame key
+    pub fn get(&self, keycode: Keycode) -> Option<KeyBinding> {
+        self.bindings
+            .get(&keycode)
+            .copied()
+            .or_else(|| map_keycode(keycode).map(KeyBinding::Pinmame))
+    }
+}
+
+fn resolve(config: BindingConfig) -> Result<KeyBinding, String> {
+    Ok(match config {
+        BindingConfig::Pinmame(target) => {
+            let target = parse_key(&target)?;
+            let pinmame = map_keycode(target)
+                .ok_or_else(|| format!("pinmame has no key {}", target.name()))?;
+            KeyBinding::Pinmame(pinmame)
+        }
+        BindingConfig::Switch(switch) => KeyBinding::Switch(switch),
+        BindingConfig::Action(action) => KeyBinding::Action(action),
+    })
+}
+
+fn parse_key(name: &str) -> Result<Keycode, String> {
+    Keycode::from_name(name).ok_or_else(|| format!("Unknown key \"{}\"", name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shipped_profile(name: &str) -> KeysFile {
+        let path = Path::new(SHIPPED_PROFILES_DIR).join(format!("{}.toml", name));
+        let content = std::fs::read_to_string(&path).unwrap();
+        toml::from_str(&content).unwrap()
+    }
+
+    #[test]
+    fn shipped_profiles_bind_the_cabinet_buttons() {
+        let wpc = shipped_profile("wpc");
+        assert!(matches!(wpc.keys["1"], BindingConfig::Switch(13)));
+        assert!(matches!(wpc.keys["Left Shift"], BindingConfig::Switch(114)));
+
+        let sys11 = shipped_profile("sys11");
+        assert!(matches!(sys11.keys["1"], BindingConfig::Switch(3)));
+
+        let stern = shipped_profile("stern");
+        for key in ["Left Shift", "Right Shift", "1", "3", "7", "8", "9", "0"] {
+            assert!(
+                matches!(&stern.keys[key], BindingConfig::Pinmame(target) if target == key),
+                "stern does not pass {} to pinmame",
+                key
+            );
+        }
+        assert!(matches!(&stern.buttons["start"], BindingConfig::Pinmame(target) if target == "1"));
+    }
+}
@@ -0,0 +1,43 @@
+use crate::{
+    console::CONSOLE_HEIGHT, dmd, libpinmame::PinmameDisplayLayout, nvram_watch::WATCH_HEIGHT,
+};
+
+// Top left positions of the panels on the screen, these depend on the size of the dmd.
+// Used for both rendering and finding what is under the mouse.
+pub struct Layout {
+    pub lamps: (u32, u32),
+    pub gi: (u32, u32),
+    pub solenoids: (u32, u32),
+    pub mechs: (u32, u32),
+    pub trough: (u32, u32),
+    pub switches: (u32, u32),
+    pub console: (u32, u32),
+    pub controls: (u32, u32),
+    pub nvram_watch: (u32, u32),
+    pub leds: (u32, u32),
+}
+
+impl Layout {
+    pub fn new(display_layout: &PinmameDisplayLayout) -> Layout {
+        let panels_y = dmd::dmd_height(display_layout) + 10;
+        Layout {
+            lamps: (0, panels_y),
+            // between the lamps and the solenoids
+            gi: (200, panels_y),
+            solenoids: (300, panels_y),
+            mechs: (0, panels_y + 110),
+            // next to the solenoids
+            trough: (490, panels_y + 20),
+            switches: (0, panels_y + 160),
+            console: (dmd::dmd_width(display_layout) + 8, 0),
+            // below the console the run controls, then the nvram watcher or the audits
+            controls: (dmd::dmd_width(display_layout) + 8, CONSOLE_HEIGHT + 4),
+            nvram_watch: (dmd::dmd_width(display_layout) + 8, CONSOLE_HEIGHT + 24),
+            // below the nvram watcher, the audits take the same height
+            leds: (
+                dmd::dmd_width(display_layout) + 8,
+                CONSOLE_HEIGHT + 24 + WATCH_HEIGHT + 8,
+            ),
+        }
+    }
+}
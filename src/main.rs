@@ -1,15 +1,15 @@
-#[macro_use]
-extern crate lazy_static;
-
 use log::{debug, error, info, trace, warn};
 use std::{
-    collections::HashMap,
     ffi::{CString, c_char, c_void},
-    sync::mpsc,
+    sync::{
+        atomic::{AtomicU8, AtomicU64, Ordering},
+        mpsc,
+    },
+    time::{Duration, Instant},
 };
 
-use sdl2::event::Event;
-use sdl2::keyboard::Keycode;
+use sdl2::event::{Event, WindowEvent};
+use sdl2::keyboard::{Keycode, Mod};
 use sdl2::{
     audio::{AudioQueue, AudioSpecDesired},
     mouse::MouseButton,
@@ -17,23 +17,38 @@ use sdl2::{
 };
 
 use libpinmame::{
-    PINMAME_AUDIO_FORMAT_PINMAME_AUDIO_FORMAT_INT16, PINMAME_KEYCODE_PINMAME_KEYCODE_ESCAPE,
-    PINMAME_KEYCODE_PINMAME_KEYCODE_MENU, PINMAME_KEYCODE_PINMAME_KEYCODE_Q, PinmameAudioInfo,
-    PinmameConfig, PinmameDisplayLayout, PinmameMechInfo,
+    PINMAME_AUDIO_FORMAT_PINMAME_AUDIO_FORMAT_INT16, PINMAME_KEYCODE_PINMAME_KEYCODE_MENU,
+    PinmameAudioInfo, PinmameConfig, PinmameDisplayLayout, PinmameMechInfo,
 };
-use pinmame::{Game, PinmameStatus};
+use pinmame::{Game, MechConfig, PinmameStatus};
 
 use crate::{
-    db::SwitchIndex,
-    keyboard::map_keycode,
-    pinmame::{
-        DmdMode, pinmame_on_console_data_updated_callback, pinmame_on_log_message_callback,
-        pinmame_on_solenoid_updated_callback,
-    },
+    altsound::{AltSound, AltSoundMode},
+    console::ConsoleLog,
+    controls::{Control, RunControl},
+    db::Names,
+    keys::{KeyBinding, KeyBindings, TesterAction},
+    layout::Layout,
+    mechs::{MechEvent, MechPanel},
+    nvram::NvramView,
+    nvram_watch::NvramWatch,
+    pinmame::{DmdMode, pinmame_on_log_message_callback, pinmame_on_solenoid_updated_callback},
+    switch_control::Debounce,
+    switch_script::SwitchScript,
+    switches::{SwitchStates, SwitchView},
+    trough::BallTrough,
 };
+mod altsound;
+mod audits;
+mod cli;
+mod console;
+mod controls;
 mod db;
 mod dmd;
+mod importer;
 mod keyboard;
+mod keys;
+mod layout;
 #[allow(
     dead_code,
     non_camel_case_types,
@@ -44,40 +59,49 @@ mod libpinmame {
     // Bindings are generated by build.rs into OUT_DIR.
     include!(concat!(env!("OUT_DIR"), "/bindings.rs"));
 }
+mod mechs;
+mod nvram;
+mod nvram_watch;
 pub mod pinmame;
+mod switch_control;
+mod switch_script;
 mod switches;
+mod text;
+mod trough;
 
 extern "C" fn pinmame_on_state_updated_callback(state: i32, _p_user_data: *mut c_void) {
     info!("OnStateUpdated(): state={}", state);
 
+    if nvram::is_probing() {
+        // the game only boots to check the nvram size of a dump, see nvram::restore
+        return;
+    }
     if state == 0 {
         warn!("OnStateUpdated(): state=0, exiting");
         std::process::exit(1);
     } else {
-        // We had to come up with our own defaults
-        // Are these correct?
-        let mech_config = libpinmame::PinmameMechConfig {
-            type_: (libpinmame::PINMAME_MECH_FLAGS_PINMAME_MECH_FLAGS_NONLINEAR
-                | libpinmame::PINMAME_MECH_FLAGS_PINMAME_MECH_FLAGS_REVERSE
-                | libpinmame::PINMAME_MECH_FLAGS_PINMAME_MECH_FLAGS_ONESOL)
-                as i32,
-            sol1: 11,
-            sol2: 0,
-            length: 240,
-            steps: 240,
-            initialPos: 0,
-            acc: 0,
-            ret: 0,
-            sw: [libpinmame::PinmameMechSwitchConfig {
-                swNo: 32,
-                startPos: 0,
-                endPos: 5,
-                pulse: 0,
-            }; 20],
-        };
-
-        unsafe {
-            libpinmame::PinmameSetMech(0, &mech_config);
+        // also when the game restarts after a reset
+        let tester = unsafe { &*(_p_user_data as *const Tester) };
+        install_mechs(&tester.mech_configs);
+    }
+}
+
+fn clear_mechs(configs: &[MechConfig]) {
+    for mech_no in 0..configs.len() {
+        if let Err(status) = pinmame::clear_mech(mech_no as i32) {
+            error!("Could not clear mech {}: {:?}", mech_no, status);
+        }
+    }
+}
+
+fn install_mechs(configs: &[MechConfig]) {
+    for (mech_no, config) in configs.iter().enumerate() {
+        match pinmame::set_mech(mech_no as i32, config) {
+            Ok(()) => match pinmame::get_mech(mech_no as i32) {
+                Ok(pos) => info!("Installed mech {} at position {}", mech_no, pos),
+                Err(status) => warn!("Installed mech {}, no position: {:?}", mech_no, status),
+            },
+            Err(status) => error!("Could not install mech {}: {:?}", mech_no, status),
         }
     }
 }
@@ -182,16 +206,9 @@ unsafe extern "C" fn pinmame_on_mech_available_callback(
     mech_info: *mut libpinmame::PinmameMechInfo,
     _user_data: *mut ::std::os::raw::c_void,
 ) {
-    // TODO not sure we need to clone here
-    // TODO do we need to free this memory?
-    let mech_info = unsafe { mech_info.as_ref().expect("mech_info is null") };
-    let safe_mech_info = PinmameMechInfo {
-        type_: mech_info.type_,
-        length: mech_info.length,
-        steps: mech_info.steps,
-        pos: mech_info.pos,
-        speed: mech_info.speed,
-    };
+    // pinmame owns the memory and only lends it for the duration of the callback,
+    // so we keep a copy
+    let safe_mech_info = unsafe { *mech_info.as_ref().expect("mech_info is null") };
 
     info!(
         "OnMechAvailable(): mechNo={}, type={}, length={}, steps={}, pos={}, speed={}",
@@ -202,8 +219,13 @@ unsafe extern "C" fn pinmame_on_mech_available_callback(
         safe_mech_info.pos,
         safe_mech_info.speed
     );
-    let tester: &mut Tester = unsafe { &mut *(_user_data as *mut Tester) };
-    tester.mech_info.push(safe_mech_info);
+    let tester = unsafe { &*(_user_data as *const Tester) };
+    if let Err(e) = tester
+        .mech_events
+        .send(MechEvent::Available(mech_no, safe_mech_info))
+    {
+        error!("Could not send mech info: {}", e);
+    }
 }
 
 //TODO make private
@@ -222,8 +244,13 @@ pub unsafe extern "C" fn pinmame_on_mech_updated_callback(
         mech_info_ref.pos,
         mech_info_ref.speed
     );
-    let tester: &mut Tester = unsafe { &mut *(_user_data as *mut Tester) };
-    tester.mech_info[mech_no as usize] = unsafe { *mech_info };
+    let tester = unsafe { &*(_user_data as *const Tester) };
+    if let Err(e) = tester
+        .mech_events
+        .send(MechEvent::Updated(mech_no, *mech_info_ref))
+    {
+        error!("Could not send mech info: {}", e);
+    }
 }
 
 extern "C" fn pinmame_on_audio_updated_callback(
@@ -237,8 +264,19 @@ extern "C" fn pinmame_on_audio_updated_callback(
 
     let samples_buffer =
         unsafe { std::slice::from_raw_parts(_buffer as *const i16, samples as usize) };
-
-    match tester.rom_audio_queue.queue_audio(samples_buffer) {
+    tester
+        .audio_samples
+        .fetch_add(samples as u64, Ordering::Relaxed);
+
+    let queued = match tester.rom_audio_volume.load(Ordering::Relaxed) {
+        100 => tester.rom_audio_queue.queue_audio(samples_buffer),
+        // muted by altsound replace mode
+        0 => Ok(()),
+        volume => tester
+            .rom_audio_queue
+            .queue_audio(&altsound::scale_samples(samples_buffer, volume)),
+    };
+    match queued {
         Ok(_) => {}
         Err(e) => {
             error!("queue_audio failed: {}", e);
@@ -253,8 +291,27 @@ extern "C" fn pinmame_on_sound_command_callback(
     cmd: ::std::os::raw::c_int,
     _p_user_data: *mut ::std::os::raw::c_void,
 ) {
-    // TODO
-    info!("OnSoundCommand(): boardNo={}, cmd={}", board_no, cmd);
+    debug!("OnSoundCommand(): boardNo={}, cmd={}", board_no, cmd);
+    let tester = unsafe { &*(_p_user_data as *const Tester) };
+    if let Err(e) = tester.sound_commands.send((board_no, cmd)) {
+        error!("sound_commands send failed: {}", e);
+    }
+}
+
+unsafe extern "C" fn pinmame_on_console_data_updated_callback(
+    data: *mut ::std::os::raw::c_void,
+    size: i32,
+    _user_data: *mut ::std::os::raw::c_void,
+) {
+    debug!("OnConsoleDataUpdated: size={}", size);
+    if data.is_null() || size <= 0 {
+        return;
+    }
+    let bytes = unsafe { std::slice::from_raw_parts(data as *const u8, size as usize) };
+    let tester = unsafe { &*(_user_data as *const Tester) };
+    if let Err(e) = tester.console_data.send(bytes.to_vec()) {
+        error!("console_data send failed: {}", e);
+    }
 }
 
 extern "C" fn pinmame_is_key_pressed_callback(
@@ -274,18 +331,82 @@ extern "C" fn pinmame_is_key_pressed_callback(
 struct Tester {
     // TODO make all this thread safe
     rom_audio_queue: AudioQueue<i16>,
+    // percentage, lowered by altsound ducking
+    rom_audio_volume: AtomicU8,
     audio_info: Option<PinmameAudioInfo>,
+    // all samples the rom produced, our clock for emulated time
+    audio_samples: AtomicU64,
+    // the clock when there is no sound, wall clock time the emulation was not paused
+    running_time: Duration,
+    last_tick: Option<Instant>,
     display_layout: Option<PinmameDisplayLayout>,
     display_data: mpsc::Sender<Vec<u8>>,
+    sound_commands: mpsc::Sender<(i32, i32)>,
+    console_data: mpsc::Sender<Vec<u8>>,
     keyboard_state: [bool; (PINMAME_KEYCODE_PINMAME_KEYCODE_MENU + 1) as usize],
-    mech_info: Vec<PinmameMechInfo>,
+    mech_events: mpsc::Sender<MechEvent>,
+    // custom mechs from the machine definition, installed when the game starts
+    mech_configs: Vec<MechConfig>,
     lamps: Vec<bool>,
     solenoids: Vec<bool>,
-    switches: HashMap<u32, bool>,
+    // brightness level per general illumination string
+    gi: Vec<i32>,
+    // lit segments per led digit
+    leds: Vec<i32>,
+    // the cores report digits for every game, only show them once the rom lights one
+    leds_seen: bool,
+}
+
+impl Tester {
+    // Advances the clock used when there is no sound, call once per frame
+    fn tick(&mut self, paused: bool) {
+        let now = Instant::now();
+        if let Some(last_tick) = self.last_tick.filter(|_| !paused) {
+            self.running_time += now - last_tick;
+        }
+        self.last_tick = Some(now);
+    }
+
+    // Time as seen by the rom, this does not advance while the emulation is not running.
+    fn emulated_time(&self) -> Duration {
+        match self.audio_info {
+            Some(audio_info) if audio_info.sampleRate > 0.0 => {
+                let samples = self.audio_samples.load(Ordering::Relaxed) as f64;
+                let channels = audio_info.channels.max(1) as f64;
+                Duration::from_secs_f64(samples / channels / audio_info.sampleRate)
+            }
+            _ => self.running_time,
+        }
+    }
 }
 
 const SCREEN_WIDTH: u32 = 800; // PIXELS_WIDTH * (PIXEL_SIZE + 1);
 const SCREEN_HEIGHT: u32 = 600; // PIXELS_HEIGHT * (PIXEL_SIZE + 1);
+const LAMP_SIZE: u32 = 8;
+
+// State that only lives on the ui thread, as opposed to the Tester which is shared
+// with the pinmame callbacks.
+#[derive(Default)]
+struct Ui {
+    console: ConsoleLog,
+    mouse: (i32, i32),
+    switch_view: SwitchView,
+    switches: SwitchStates,
+    // the switch held by the left mouse button
+    held_switch: Option<u32>,
+    trough: Option<BallTrough>,
+    mechs: MechPanel,
+    // the nvram grid replaces the switches
+    show_nvram: bool,
+    nvram: NvramView,
+    nvram_watch: NvramWatch,
+    show_audits: bool,
+    // from the machine definition, plus the ones named with the watcher
+    nvram_fields: Vec<db::NvramField>,
+    // the quit confirmation is showing
+    confirm_quit: bool,
+    run: RunControl,
+}
 
 fn main() -> Result<(), String> {
     // run me like this: RUST_LOG=info cargo run
@@ -295,6 +416,12 @@ fn main() -> Result<(), String> {
 
     pretty_env_logger::init();
 
+    let options = match cli::parse_args(std::env::args().skip(1))? {
+        cli::Command::Run(options) => options,
+        cli::Command::Import(args) => return importer::import(&args),
+        cli::Command::Nv(args) => return audits::inspect(&args),
+    };
+
     // TODO we should get this from the loaded rom, or update the window when we get it
     let (sdl_context, mut canvas) = setup_sdl2(SCREEN_WIDTH, SCREEN_HEIGHT)?;
     let mut events = sdl_context.event_pump()?;
@@ -309,6 +436,9 @@ fn main() -> Result<(), String> {
     //font.set_style(sdl2::ttf::FontStyle::BOLD);
 
     let (dmd_tx, dmd_rx) = mpsc::channel::<Vec<u8>>();
+    let (sound_command_tx, sound_command_rx) = mpsc::channel::<(i32, i32)>();
+    let (console_tx, console_rx) = mpsc::channel::<Vec<u8>>();
+    let (mech_tx, mech_rx) = mpsc::channel::<MechEvent>();
 
     // TODO we need to get this from the rom
     let desired_spec = AudioSpecDesired {
@@ -323,14 +453,23 @@ fn main() -> Result<(), String> {
 
     let mut tester = Tester {
         rom_audio_queue: device,
+        rom_audio_volume: AtomicU8::new(100),
         audio_info: None,
+        audio_samples: AtomicU64::new(0),
+        running_time: Duration::ZERO,
+        last_tick: None,
         display_layout: None,
         display_data: dmd_tx,
+        sound_commands: sound_command_tx,
+        console_data: console_tx,
         keyboard_state: [false; (PINMAME_KEYCODE_PINMAME_KEYCODE_MENU + 1) as usize],
-        mech_info: Vec::new(),
+        mech_events: mech_tx,
+        mech_configs: Vec::new(),
         lamps: Vec::new(),
         solenoids: Vec::new(),
-        switches: HashMap::new(),
+        gi: Vec::new(),
+        leds: Vec::new(),
+        leds_seen: false,
     };
 
     // get home directory
@@ -384,17 +523,36 @@ fn main() -> Result<(), String> {
 
     // Terminator 2
     let p_name = "t2_l8";
-    let switch_index = &db::T2_SWITCHES;
 
     // Medieval Madness
     // let p_name = "mm_109c";
-    // let switch_index = &db::MM_SWITCHES;
+
+    let mut altsound = match options.altsound {
+        Some(mode) => {
+            let altsound_path = options
+                .altsound_path
+                .clone()
+                .unwrap_or_else(|| pinmame_path.join("altsound").join(p_name));
+            match AltSound::load(&altsound_path, mode) {
+                Ok(altsound) => {
+                    if altsound.mode() == AltSoundMode::Replace {
+                        tester.rom_audio_volume.store(0, Ordering::Relaxed);
+                    }
+                    Some(altsound)
+                }
+                Err(e) => {
+                    error!("Could not load altsound {}: {}", altsound_path.display(), e);
+                    None
+                }
+            }
+        }
+        None => None,
+    };
 
     pinmame::set_config(&config);
 
     pinmame::set_user_data(&tester as *const Tester as *mut std::ffi::c_void);
     pinmame::set_handle_keyboard(false);
-    pinmame::set_handle_mechanics(true);
 
     pinmame::set_dmd_mode(DmdMode::Raw);
     match pinmame::get_games() {
@@ -411,17 +569,71 @@ fn main() -> Result<(), String> {
         }
     }
 
-    match pinmame::get_game(p_name) {
+    let clone_of = match pinmame::get_game(p_name) {
         Ok(game) => {
+            let clone_of = game.clone_of.clone();
             info!("Found game for {}: {}", p_name, describe_game(game));
+            Some(clone_of)
         }
         Err(status) => {
             error!("Could not find game {}: {:?}", p_name, status);
+            None
+        }
+    };
+
+    let (machine, machine_path) = match db::load_machine(
+        &db::machine_dirs(&pinmame_path),
+        p_name,
+        clone_of.as_deref(),
+    )? {
+        Some(loaded) => {
+            let machine = &loaded.machine;
+            info!(
+                "Using machine definition \"{}\" from {} ({} switches, {} lamps, {} coils, {} flashers, {} gi strings)",
+                machine.name,
+                loaded.path.display(),
+                machine.switches.len(),
+                machine.lamps.len(),
+                machine.coils.len(),
+                machine.flashers.len(),
+                machine.gi.len()
+            );
+            (loaded.machine, Some(loaded.path))
+        }
+        None => {
+            warn!("No machine definition found for {}", p_name);
+            (db::Machine::default(), None)
         }
+    };
+    let names = machine.names();
+
+    tester.mech_configs = machine
+        .mechs
+        .iter()
+        .map(MechConfig::try_from)
+        .collect::<Result<_, _>>()?;
+    // pinmame refuses custom mechs while it handles the mechanics
+    if !tester.mech_configs.is_empty() {
+        warn!(
+            "{} custom mechs defined, the mechanics built into pinmame are switched off for all mechs of this game",
+            tester.mech_configs.len()
+        );
+    }
+    pinmame::set_handle_mechanics(tester.mech_configs.is_empty());
+
+    let mut ui = Ui {
+        mechs: MechPanel::new(machine.mechs.clone()),
+        nvram_fields: machine.nvram_fields.clone(),
+        ..Default::default()
+    };
+
+    if let Some(dump) = &options.nvram {
+        nvram::restore(dump, &pinmame_path, p_name)?;
     }
 
     if pinmame::run(p_name) == PinmameStatus::Ok {
-        info!("PinmameRun succeeded")
+        info!("PinmameRun succeeded");
+        apply_startup(&machine, &mut ui);
     } else {
         error!("PinmameRun failed");
     }
@@ -434,16 +646,27 @@ fn main() -> Result<(), String> {
     info!("max_solenoids: {}", max_solenoids);
     tester.solenoids = vec![false; max_solenoids as usize];
 
-    // Close the coin door for Medieval Madness
-    //pinmame::set_switch(22, 1);
+    let mut key_bindings = KeyBindings::load(
+        &pinmame_path,
+        p_name,
+        machine.key_profile.as_deref(),
+        machine_path.as_deref(),
+    )?;
+    let game_controllers = sdl_context.game_controller()?;
+    let mut controllers = Vec::new();
+    let pulse = Duration::from_millis(options.pulse_ms);
+    let mut display_data = vec![0; 128 * 32];
 
-    // Rescu911 Switch short return 5
-    // set all switches to 1
-    // for i in 0..63 {
-    //     pinmame::set_switch(i, 1);
-    // }
+    let mut switch_script = match &options.switch_script {
+        Some(path) => {
+            info!("Using switch script {}", path.display());
+            Some(SwitchScript::load(path)?.start(ui.switches.controller()))
+        }
+        None => None,
+    };
 
-    let mut display_data = vec![0; 128 * 32];
+    // the run status in the window title
+    let mut shown_status = "";
 
     'main: loop {
         // get the inputs here
@@ -454,37 +677,288 @@ fn main() -> Result<(), String> {
                 Event::KeyDown {
                     keycode: Some(keycode),
                     ..
-                } => {
-                    match map_keycode(keycode) {
-                        Some(keycode) => {
-                            tester.keyboard_state[keycode as usize] = true;
+                } if ui.confirm_quit => match keycode {
+                    Keycode::Y | Keycode::Return | Keycode::KpEnter => break 'main,
+                    Keycode::N | Keycode::Escape => ui.confirm_quit = false,
+                    _ => {}
+                },
+
+                // the tester commands use ctrl, the rom gets Escape and Q
+                Event::KeyDown {
+                    keycode: Some(Keycode::Q),
+                    keymod,
+                    ..
+                } if keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) => {
+                    ui.confirm_quit = true;
+                }
+
+                // typing the name of an nvram field, keep the keys away from the rom
+                Event::TextInput { text, .. } if ui.nvram_watch.is_naming() => {
+                    ui.nvram_watch.type_text(&text);
+                }
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    ..
+                } if ui.nvram_watch.is_naming() => match keycode {
+                    Keycode::Return | Keycode::KpEnter => {
+                        if let Some((change, name)) = ui.nvram_watch.finish_naming() {
+                            let field = db::NvramField {
+                                name,
+                                address: change.address as u32,
+                                length: change.new.len() as u32,
+                                encoding: db::NvramEncoding::default(),
+                            };
+                            match db::add_nvram_field(
+                                &pinmame_path,
+                                p_name,
+                                machine_path.as_deref(),
+                                &field,
+                            ) {
+                                Ok(path) => {
+                                    info!("Saved nvram field {} to {}", field.name, path.display());
+                                    ui.nvram_fields.push(field);
+                                }
+                                Err(e) => error!("Could not save nvram field: {}", e),
+                            }
                         }
-                        None => warn!("KeyDown keycode not mapped: {:?}", keycode),
                     }
-                    match keycode {
-                        Keycode::Escape => {
-                            tester.keyboard_state
-                                [PINMAME_KEYCODE_PINMAME_KEYCODE_ESCAPE as usize] = true;
-                            break 'main;
+                    Keycode::Escape => ui.nvram_watch.cancel_naming(),
+                    Keycode::Backspace => ui.nvram_watch.backspace(),
+                    _ => {}
+                },
+
+                Event::KeyDown {
+                    keycode: Some(Keycode::A),
+                    keymod,
+                    ..
+                } if keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) => {
+                    if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+                        let timestamp = std::time::SystemTime::now()
+                            .duration_since(std::time::UNIX_EPOCH)
+                            .map(|d| d.as_secs())
+                            .unwrap_or(0);
+                        let path = pinmame_path
+                            .join("nvram")
+                            .join("audits")
+                            .join(format!("{}-{}.json", p_name, timestamp));
+                        match audits::export(&path, &ui.nvram_fields, ui.nvram.bytes()) {
+                            Ok(()) => info!("Saved audits to {}", path.display()),
+                            Err(e) => error!("Could not save audits: {}", e),
                         }
-                        Keycode::Q => {
-                            tester.keyboard_state[PINMAME_KEYCODE_PINMAME_KEYCODE_Q as usize] =
-                                true;
-                            break 'main;
+                    } else {
+                        ui.show_audits = !ui.show_audits;
+                    }
+                }
+
+                Event::KeyDown {
+                    keycode: Some(Keycode::W),
+                    keymod,
+                    ..
+                } if keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) => {
+                    if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+                        ui.nvram_watch.stop();
+                    } else {
+                        info!("Marked nvram baseline");
+                        ui.nvram_watch.mark(ui.nvram.bytes());
+                    }
+                }
+
+                Event::KeyDown {
+                    keycode: Some(Keycode::S),
+                    keymod,
+                    ..
+                } if keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) => {
+                    let timestamp = std::time::SystemTime::now()
+                        .duration_since(std::time::UNIX_EPOCH)
+                        .map(|d| d.as_secs())
+                        .unwrap_or(0);
+                    let path = pinmame_path
+                        .join("console")
+                        .join(format!("{}-{}.bin", p_name, timestamp));
+                    match ui.console.save(&path) {
+                        Ok(()) => info!("Saved console data to {}", path.display()),
+                        Err(e) => error!("Could not save console data: {}", e),
+                    }
+                }
+
+                Event::KeyDown {
+                    keycode: Some(Keycode::N),
+                    keymod,
+                    ..
+                } if keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) => {
+                    if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+                        let timestamp = std::time::SystemTime::now()
+                            .duration_since(std::time::UNIX_EPOCH)
+                            .map(|d| d.as_secs())
+                            .unwrap_or(0);
+                        let path = pinmame_path
+                            .join("nvram")
+                            .join("dumps")
+                            .join(format!("{}-{}.nv", p_name, timestamp));
+                        match ui.nvram.save(&path) {
+                            Ok(()) => info!("Saved nvram to {}", path.display()),
+                            Err(e) => error!("Could not save nvram: {}", e),
                         }
-                        _ => {}
+                    } else {
+                        ui.show_nvram = !ui.show_nvram;
                     }
-                    //println!("key down: {:?}", keycode);
                 }
-                Event::KeyUp { keycode, .. } => match keycode {
-                    Some(keycode) => match map_keycode(keycode) {
-                        Some(keycode) => {
-                            tester.keyboard_state[keycode as usize] = false;
+
+                Event::KeyDown {
+                    keycode: Some(Keycode::B),
+                    keymod,
+                    ..
+                } if keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) => {
+                    let controller = ui.switches.controller();
+                    let debounce = match controller.debounce() {
+                        Some(_) => None,
+                        None => Some(Debounce::default()),
+                    };
+                    info!("Switch bounce emulation: {:?}", debounce);
+                    controller.set_debounce(debounce);
+                }
+
+                Event::KeyDown {
+                    keycode: Some(keycode @ (Keycode::D | Keycode::L)),
+                    keymod,
+                    ..
+                } if keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) => {
+                    if let Some(trough) = ui.trough.as_mut() {
+                        let controller = ui.switches.controller();
+                        match keycode {
+                            Keycode::D => trough.drain(controller),
+                            _ => trough.launch(controller),
                         }
-                        None => warn!("KeyUp keycode not mapped: {:?}", keycode),
-                    },
-                    None => (),
+                    }
+                }
+
+                Event::KeyDown {
+                    keycode: Some(Keycode::P),
+                    keymod,
+                    ..
+                } if keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) => {
+                    if keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD) {
+                        ui.run.run(Control::Step, tester.emulated_time());
+                    } else {
+                        ui.run.run(Control::PauseResume, tester.emulated_time());
+                    }
+                }
+
+                Event::KeyDown {
+                    keycode: Some(Keycode::R),
+                    keymod,
+                    ..
+                } if keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) => {
+                    ui.run.run(Control::Reset, tester.emulated_time());
+                }
+
+                Event::KeyDown {
+                    keycode: Some(Keycode::M),
+                    keymod,
+                    ..
+                } if keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD) => {
+                    ui.switch_view = ui.switch_view.toggled();
+                    info!("Switch view: {:?}", ui.switch_view);
+                }
+
+                Event::KeyDown {
+                    keycode: Some(keycode),
+                    repeat,
+                    ..
+                } => match key_bindings.get(keycode) {
+                    Some(binding) if !repeat => handle_binding(
+                        binding,
+                        true,
+                        &mut tester,
+                        &mut ui,
+                        &canvas,
+                        &pinmame_path,
+                        p_name,
+                    ),
+                    Some(_) => {}
+                    None => warn!("KeyDown keycode not mapped: {:?}", keycode),
                 },
+                Event::KeyUp {
+                    keycode: Some(keycode),
+                    ..
+                } => match key_bindings.get(keycode) {
+                    Some(binding) => handle_binding(
+                        binding,
+                        false,
+                        &mut tester,
+                        &mut ui,
+                        &canvas,
+                        &pinmame_path,
+                        p_name,
+                    ),
+                    None => warn!("KeyUp keycode not mapped: {:?}", keycode),
+                },
+
+                // controllers that are connected at startup are added too
+                Event::ControllerDeviceAdded { which, .. } => match game_controllers.open(which) {
+                    Ok(controller) => {
+                        info!("Using game controller {}", controller.name());
+                        controllers.push(controller);
+                    }
+                    Err(e) => warn!("Could not open game controller {}: {}", which, e),
+                },
+                Event::ControllerDeviceRemoved { which, .. } => {
+                    controllers.retain(|controller| controller.instance_id() != which);
+                }
+                Event::ControllerButtonDown { button, .. }
+                | Event::ControllerButtonUp { button, .. } => {
+                    let pressed = matches!(event, Event::ControllerButtonDown { .. });
+                    if let Some(binding) = key_bindings.button(button) {
+                        handle_binding(
+                            binding,
+                            pressed,
+                            &mut tester,
+                            &mut ui,
+                            &canvas,
+                            &pinmame_path,
+                            p_name,
+                        );
+                    }
+                }
+                Event::ControllerAxisMotion { axis, value, .. } => {
+                    for (binding, pressed) in key_bindings.axis_motion(axis, value) {
+                        handle_binding(
+                            binding,
+                            pressed,
+                            &mut tester,
+                            &mut ui,
+                            &canvas,
+                            &pinmame_path,
+                            p_name,
+                        );
+                    }
+                }
+
+                Event::MouseWheel {
+                    y,
+                    mouse_x,
+                    mouse_y,
+                    ..
+                } => {
+                    if let Some(display_layout) = tester.display_layout {
+                        let layout = Layout::new(&display_layout);
+                        let (console_at_x, console_at_y) = layout.console;
+                        if console::is_over_console(mouse_x, mouse_y, console_at_x, console_at_y) {
+                            ui.console.scroll(y);
+                        }
+                        let (nvram_at_x, nvram_at_y) = layout.switches;
+                        if ui.show_nvram
+                            && nvram::is_over_nvram(mouse_x, mouse_y, nvram_at_x, nvram_at_y)
+                        {
+                            // wheel up shows the lower addresses
+                            ui.nvram.scroll(-y);
+                        }
+                    }
+                }
+
+                Event::MouseMotion { x, y, .. } => {
+                    ui.mouse = (x, y);
+                }
 
                 Event::MouseButtonDown {
                     x, y, mouse_btn, ..
@@ -496,31 +970,55 @@ fn main() -> Result<(), String> {
                     // lastx = x as i16;
                     // lasty = y as i16;
                     // println!("mouse btn down at ({},{})", x, y);
-                    let s = pinmame::get_switch(16);
-                    println!("switch 16: {}", s);
-
                     if let Some(display_layout) = tester.display_layout {
-                        let switches_at_x = 0;
-                        let switches_at_y =
-                            (dmd::dmd_height(&display_layout) + 10 + 100 + 50) as i32;
-                        if let Some(switch) = switches::switch_id_for_mouse(
-                            x - switches_at_x,
-                            y - switches_at_y,
-                            switch_index,
+                        let (controls_at_x, controls_at_y) = Layout::new(&display_layout).controls;
+                        if let Some(control) = controls::control_for_mouse(
+                            x - controls_at_x as i32,
+                            y - controls_at_y as i32,
                         ) {
+                            ui.run.run(control, tester.emulated_time());
+                        }
+                    }
+                    if let Some(display_layout) =
+                        tester.display_layout.filter(|_| ui.nvram_watch.is_active())
+                    {
+                        let (watch_at_x, watch_at_y) = Layout::new(&display_layout).nvram_watch;
+                        if let Some(change) = nvram_watch::change_for_mouse(
+                            x - watch_at_x as i32,
+                            y - watch_at_y as i32,
+                            &ui.nvram_watch,
+                            ui.nvram.bytes(),
+                        ) {
+                            ui.nvram_watch.start_naming(change);
+                        }
+                    }
+                    if let Some(display_layout) = tester.display_layout.filter(|_| !ui.show_nvram) {
+                        let (switches_at_x, switches_at_y) = Layout::new(&display_layout).switches;
+                        if let Some(switch) = switches::switch_id_for_mouse_in_view(
+                            ui.switch_view,
+                            x - switches_at_x as i32,
+                            y - switches_at_y as i32,
+                            &names.switches,
+                        ) {
+                            let shift = sdl_context
+                                .keyboard()
+                                .mod_state()
+                                .intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD);
+                            let pulse_click = mouse_btn == MouseButton::Middle
+                                || (mouse_btn == MouseButton::Left && shift);
                             match mouse_btn {
+                                _ if pulse_click => {
+                                    debug!("switch {} pulse", switch);
+                                    ui.switches.controller().pulse(switch, pulse);
+                                }
                                 MouseButton::Left => {
-                                    println!("switch {} true", switch);
-                                    tester.switches.insert(switch, true);
-                                    pinmame::set_switch(switch as i32, 1);
+                                    debug!("switch {} true", switch);
+                                    ui.switches.hold(switch);
+                                    ui.held_switch = Some(switch);
                                 }
                                 MouseButton::Right => {
-                                    let switched =
-                                        *tester.switches.get(&switch).unwrap_or(&false) ^ true;
-                                    println!("switch {} toggled to {}", switch, switched);
-                                    tester.switches.insert(switch, switched);
-
-                                    pinmame::set_switch(switch as i32, switched as i32);
+                                    let switched = ui.switches.toggle(switch);
+                                    debug!("switch {} toggled to {}", switch, switched);
                                 }
                                 _ => (),
                             }
@@ -528,29 +1026,18 @@ fn main() -> Result<(), String> {
                     }
                 }
 
+                // release wherever the mouse is, or when we lose focus while holding
                 Event::MouseButtonUp {
-                    x, y, mouse_btn, ..
+                    mouse_btn: MouseButton::Left,
+                    ..
+                }
+                | Event::Window {
+                    win_event: WindowEvent::FocusLost,
+                    ..
                 } => {
-                    // println!("mouse btn up at ({},{})", x, y);
-
-                    if let Some(display_layout) = tester.display_layout {
-                        let switches_at_x = 0;
-                        let switches_at_y =
-                            (dmd::dmd_height(&display_layout) + 10 + 100 + 50) as i32;
-                        if let Some(switch) = switches::switch_id_for_mouse(
-                            x - switches_at_x,
-                            y - switches_at_y,
-                            switch_index,
-                        ) {
-                            match mouse_btn {
-                                MouseButton::Left => {
-                                    tester.switches.insert(switch, false);
-                                    println!("switch {} false", switch);
-                                    pinmame::set_switch(switch as i32, 0);
-                                }
-                                _ => (),
-                            }
-                        }
+                    if let Some(switch) = ui.held_switch.take() {
+                        debug!("switch {} false", switch);
+                        ui.switches.release(switch);
                     }
                 }
 
@@ -560,6 +1047,53 @@ fn main() -> Result<(), String> {
 
         // update the game loop here
 
+        tester.tick(pinmame::is_paused());
+        let now = tester.emulated_time();
+        ui.run.update(now);
+        if ui.run.take_reset_request() {
+            reset_machine(&machine, &mut tester, &mut ui, &mech_rx);
+            if let Some(script) = switch_script.as_mut() {
+                info!("Restarting the switch script");
+                script.restart(now);
+            }
+        }
+        let status = ui.run.status();
+        if status != shown_status {
+            let title = format!("Pinmame rom tester - {} ({})", p_name, status);
+            if let Err(e) = canvas.window_mut().set_title(&title) {
+                warn!("Could not set the window title: {}", e);
+            }
+            shown_status = status;
+        }
+
+        // kept when done, a reset runs it again
+        if let Some(script) = switch_script.as_mut().filter(|script| !script.is_done()) {
+            script.update(now, ui.switches.controller());
+            if script.is_done() {
+                info!("Switch script done");
+            }
+        }
+        ui.switches.update(now, &names.switches);
+        while let Ok(event) = mech_rx.try_recv() {
+            ui.mechs.handle(event);
+        }
+
+        ui.nvram.update();
+
+        while let Ok(bytes) = console_rx.try_recv() {
+            ui.console.push(&bytes);
+        }
+
+        while let Ok((board_no, cmd)) = sound_command_rx.try_recv() {
+            if let Some(altsound) = altsound.as_mut() {
+                altsound.handle_command(board_no, cmd);
+            }
+        }
+        if let Some(altsound) = altsound.as_mut() {
+            let volume = altsound.update();
+            tester.rom_audio_volume.store(volume, Ordering::Relaxed);
+        }
+
         let changed_lamps = pinmame::get_changed_lamps();
         if !changed_lamps.is_empty() {
             //info!("Update for {} lamps", changed_lamps.len());
@@ -572,6 +1106,38 @@ fn main() -> Result<(), String> {
             }
         }
 
+        // the counts are only known once the game runs, see NvramView::update
+        if tester.gi.is_empty() {
+            let max_gis = pinmame::get_max_gis();
+            if max_gis > 0 {
+                info!("max_gis: {}", max_gis);
+                tester.gi = vec![0; max_gis as usize];
+            }
+        }
+        for gi in pinmame::get_changed_gis() {
+            match tester.gi.get_mut(gi.giNo as usize) {
+                Some(level) => *level = gi.state,
+                None => warn!("GI {} out of range", gi.giNo),
+            }
+        }
+
+        if tester.leds.is_empty() {
+            let max_leds = pinmame::get_max_leds();
+            if max_leds > 0 {
+                info!("max_leds: {}", max_leds);
+                tester.leds = vec![0; max_leds as usize];
+            }
+        }
+        for led in pinmame::get_changed_leds() {
+            match tester.leds.get_mut(led.ledNo as usize) {
+                Some(segments) => {
+                    *segments = led.state;
+                    tester.leds_seen |= led.state != 0;
+                }
+                None => warn!("LED {} out of range", led.ledNo),
+            }
+        }
+
         // TODO we also have a callback for solenoid updates, which should we be using?
         let changed_solenoids = pinmame::get_changed_solenoids();
         if !changed_solenoids.is_empty() {
@@ -580,13 +1146,23 @@ fn main() -> Result<(), String> {
                 if solenoid.state != 0 && solenoid.state != 1 {
                     info!("solenoid {}: {}", solenoid.solNo, solenoid.state);
                 }
-                tester.solenoids[solenoid.solNo as usize] = solenoid.state != 0;
+                let Some(on) = tester.solenoids.get_mut(solenoid.solNo as usize) else {
+                    warn!("Solenoid {} out of range", solenoid.solNo);
+                    continue;
+                };
+                let was_on = std::mem::replace(on, solenoid.state != 0);
+                if let Some(trough) = ui.trough.as_mut() {
+                    trough.on_solenoid(
+                        solenoid.solNo as u32,
+                        was_on,
+                        solenoid.state != 0,
+                        ui.switches.controller(),
+                    );
+                }
                 //info!("solenoid {}: {}", solenoid.solNo, solenoid.state);
             }
         }
 
-        let lamp_size = 8;
-
         if let Some(display_layout) = tester.display_layout {
             match dmd_rx.try_recv() {
                 Ok(received_display_data) => {
@@ -604,8 +1180,8 @@ fn main() -> Result<(), String> {
                 &display_data,
                 display_layout,
                 &tester,
-                lamp_size,
-                switch_index,
+                &names,
+                &ui,
             )?;
         } else {
             info!("display_layout is None");
@@ -623,46 +1199,310 @@ fn render(
     display_data: &[u8],
     display_layout: PinmameDisplayLayout,
     tester: &Tester,
-    lamp_size: u32,
-    switch_index: &SwitchIndex,
+    names: &Names,
+    ui: &Ui,
 ) -> Result<(), String> {
+    let layout = Layout::new(&display_layout);
     canvas.set_draw_color(pixels::Color::RGB(0, 0, 0));
     canvas.clear();
     dmd::render_dmd(0, 0, display_data, &display_layout, canvas)?;
     dmd::render_lights(
-        0,
-        dmd::dmd_height(&display_layout) + 10,
+        layout.lamps.0,
+        layout.lamps.1,
         &tester.lamps,
+        &names.lamps,
         canvas,
-        lamp_size,
+        font,
+        LAMP_SIZE,
     )?;
-    dmd::render_solenoids(
-        300,
-        dmd::dmd_height(&display_layout) + 10,
-        &tester.solenoids,
+    dmd::render_gi(
+        layout.gi.0,
+        layout.gi.1,
+        &tester.gi,
+        &names.gi,
         canvas,
-        lamp_size,
+        font,
     )?;
-    dmd::render_mechs(
-        0,
-        dmd::dmd_height(&display_layout) + 10 + 100,
-        &tester.mech_info,
+    if tester.leds_seen {
+        dmd::render_leds(layout.leds.0, layout.leds.1, &tester.leds, canvas, font)?;
+    }
+    dmd::render_solenoids(
+        layout.solenoids.0,
+        layout.solenoids.1,
+        &tester.solenoids,
+        &names.solenoids,
         canvas,
+        font,
+        LAMP_SIZE,
     )?;
+    ui.mechs
+        .render(layout.mechs.0, layout.mechs.1, &ui.switches, canvas, font)?;
+
+    match ui.switch_view {
+        _ if ui.show_nvram => nvram::render_nvram(
+            layout.switches.0,
+            layout.switches.1,
+            &ui.nvram,
+            canvas,
+            font,
+        )?,
+        SwitchView::List => switches::render_switches(
+            layout.switches.0,
+            layout.switches.1,
+            canvas,
+            font,
+            &names.switches,
+            &ui.switches,
+        )?,
+        SwitchView::Matrix => switches::render_switch_matrix(
+            layout.switches.0,
+            layout.switches.1,
+            canvas,
+            font,
+            &names.switches,
+            &ui.switches,
+        )?,
+    }
 
-    switches::render_switches(
-        0,
-        dmd::dmd_height(&display_layout) + 10 + 100 + 50,
+    console::render_console(
+        layout.console.0,
+        layout.console.1,
+        &ui.console,
         canvas,
         font,
-        switch_index,
-        &tester.switches,
     )?;
 
+    if ui.nvram_watch.is_active() {
+        nvram_watch::render_watch(
+            layout.nvram_watch.0,
+            layout.nvram_watch.1,
+            &ui.nvram_watch,
+            ui.nvram.bytes(),
+            canvas,
+            font,
+        )?;
+    } else if ui.show_audits {
+        audits::render_audits(
+            layout.nvram_watch.0,
+            layout.nvram_watch.1,
+            &ui.nvram_fields,
+            ui.nvram.bytes(),
+            canvas,
+            font,
+        )?;
+    }
+
+    controls::render_controls(layout.controls.0, layout.controls.1, canvas, font)?;
+
+    if let Some(trough) = &ui.trough {
+        let mut line_y = layout.trough.1 as i32;
+        for line in trough.status() {
+            let (_, height) = text::render_text(
+                layout.trough.0 as i32,
+                line_y,
+                &line,
+                pixels::Color::RGB(150, 150, 150),
+                canvas,
+                font,
+            )?;
+            line_y += height as i32;
+        }
+    }
+
+    if ui.confirm_quit {
+        let lines = [
+            "Quit the tester?".to_string(),
+            "Y to quit, N to continue".to_string(),
+        ];
+        text::render_tooltip(
+            SCREEN_WIDTH as i32 / 2 - 70,
+            SCREEN_HEIGHT as i32 / 2 - 20,
+            &lines,
+            canvas,
+            font,
+        )?;
+    } else if let Some(lines) = tooltip_lines(&layout, tester, names, ui) {
+        text::render_tooltip(ui.mouse.0 + 12, ui.mouse.1 + 12, &lines, canvas, font)?;
+    }
+
     canvas.present();
     Ok(())
 }
 
+// Describes the lamp, solenoid, GI string or matrix switch under the mouse
+fn tooltip_lines(layout: &Layout, tester: &Tester, names: &Names, ui: &Ui) -> Option<Vec<String>> {
+    let (x, y) = ui.mouse;
+    if ui.show_nvram {
+        let (at_x, at_y) = layout.switches;
+        if let Some(address) = nvram::address_for_mouse(x - at_x as i32, y - at_y as i32, &ui.nvram)
+        {
+            let value = ui.nvram.bytes()[address];
+            return Some(vec![format!(
+                "NVRAM 0x{:04x}: 0x{:02x} ({})",
+                address, value, value
+            )]);
+        }
+    } else if ui.switch_view == SwitchView::Matrix {
+        let (at_x, at_y) = layout.switches;
+        if let Some(switch) = switches::matrix_switch_id_for_mouse(x - at_x as i32, y - at_y as i32)
+        {
+            let name = names
+                .switches
+                .get(&switch)
+                .map(String::as_str)
+                .unwrap_or("(unnamed)");
+            let state = if ui.switches.is_closed(switch) {
+                "closed"
+            } else {
+                "open"
+            };
+            let source = if ui.switches.is_forced(switch) {
+                "forced"
+            } else {
+                "emulator"
+            };
+            return Some(vec![
+                format!("Switch {}: {}", switch, name),
+                format!("state: {} ({})", state, source),
+            ]);
+        }
+    }
+    if let Some(gi_no) = dmd::gi_number_for_mouse(
+        x - layout.gi.0 as i32,
+        y - layout.gi.1 as i32,
+        tester.gi.len(),
+    ) {
+        let name = names
+            .gi
+            .get(&(gi_no as u32))
+            .map(String::as_str)
+            .unwrap_or("(unnamed)");
+        return Some(vec![
+            format!("GI {}: {}", gi_no, name),
+            format!("brightness: {}/{}", tester.gi[gi_no], dmd::GI_MAX_LEVEL),
+        ]);
+    }
+    let panels = [
+        ("Lamp", layout.lamps, &tester.lamps, &names.lamps),
+        (
+            "Solenoid",
+            layout.solenoids,
+            &tester.solenoids,
+            &names.solenoids,
+        ),
+    ];
+    for (kind, (at_x, at_y), states, names) in panels {
+        if let Some(number) =
+            dmd::grid_number_for_mouse(x - at_x as i32, y - at_y as i32, LAMP_SIZE, states.len())
+        {
+            let name = names
+                .get(&(number as u32))
+                .map(String::as_str)
+                .unwrap_or("(unnamed)");
+            let state = if states[number] { "on" } else { "off" };
+            return Some(vec![
+                format!("{} {}: {}", kind, number, name),
+                format!("state: {}", state),
+            ]);
+        }
+    }
+    None
+}
+
+// A bound key or game controller input got pressed or released
+fn handle_binding(
+    binding: KeyBinding,
+    pressed: bool,
+    tester: &mut Tester,
+    ui: &mut Ui,
+    canvas: &sdl2::render::Canvas<sdl2::video::Window>,
+    pinmame_path: &std::path::Path,
+    rom: &str,
+) {
+    match binding {
+        KeyBinding::Pinmame(pinmame_keycode) => {
+            tester.keyboard_state[pinmame_keycode as usize] = pressed;
+        }
+        KeyBinding::Switch(switch) if pressed => ui.switches.hold(switch),
+        KeyBinding::Switch(switch) => ui.switches.release(switch),
+        KeyBinding::Action(_) if !pressed => {}
+        KeyBinding::Action(TesterAction::Pause) => {
+            ui.run.run(Control::PauseResume, tester.emulated_time())
+        }
+        KeyBinding::Action(TesterAction::Step) => ui.run.run(Control::Step, tester.emulated_time()),
+        KeyBinding::Action(TesterAction::Reset) => {
+            ui.run.run(Control::Reset, tester.emulated_time())
+        }
+        KeyBinding::Action(TesterAction::Quit) => ui.confirm_quit = true,
+        KeyBinding::Action(TesterAction::Screenshot) => {
+            let timestamp = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_secs())
+                .unwrap_or(0);
+            let path = pinmame_path
+                .join("tester")
+                .join("screenshots")
+                .join(format!("{}-{}.bmp", rom, timestamp));
+            match save_screenshot(canvas, &path) {
+                Ok(()) => info!("Saved screenshot to {}", path.display()),
+                Err(e) => error!("Could not save screenshot: {}", e),
+            }
+        }
+    }
+}
+
+// Closes the startup switches and fills the trough, after booting and after a reset
+fn apply_startup(machine: &db::Machine, ui: &mut Ui) {
+    let closed_switches = &machine.startup.closed_switches;
+    if !closed_switches.is_empty() {
+        info!("Closing startup switches {:?}", closed_switches);
+        let changes: Vec<(u32, bool)> = closed_switches.iter().map(|sw| (*sw, true)).collect();
+        ui.switches.controller().set_many(&changes);
+    }
+    if let Some(config) = &machine.trough {
+        let trough = BallTrough::new(config.clone());
+        trough.start(ui.switches.controller());
+        ui.trough = Some(trough);
+    }
+}
+
+fn reset_machine(
+    machine: &db::Machine,
+    tester: &mut Tester,
+    ui: &mut Ui,
+    mech_rx: &mpsc::Receiver<MechEvent>,
+) {
+    info!("Resetting the machine");
+    // the state callback installs the mechs again once the game restarts, the events
+    // queued until now are from before the reset
+    clear_mechs(&tester.mech_configs);
+    while mech_rx.try_recv().is_ok() {}
+    ui.mechs.clear();
+    pinmame::reset();
+
+    tester.lamps.fill(false);
+    tester.solenoids.fill(false);
+    tester.gi.fill(0);
+    tester.leds.fill(0);
+    ui.held_switch = None;
+    ui.switches.reset();
+    apply_startup(machine, ui);
+}
+
+fn save_screenshot(
+    canvas: &sdl2::render::Canvas<sdl2::video::Window>,
+    path: &std::path::Path,
+) -> Result<(), String> {
+    let (width, height) = canvas.output_size()?;
+    let format = pixels::PixelFormatEnum::ARGB8888;
+    let mut data = canvas.read_pixels(None, format)?;
+    let surface = sdl2::surface::Surface::from_data(&mut data, width, height, width * 4, format)?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    surface.save_bmp(path)
+}
+
 fn describe_game(game: Game) -> String {
     format!(
         "name={}, description={}, manufacturer={}, year={}, flags={}, found={}",
@@ -0,0 +1,262 @@
+use std::collections::BTreeMap;
+
+use log::debug;
+
+use sdl2::{gfx::primitives::DrawRenderer, pixels::Color, rect::Rect};
+
+use crate::{
+    db::{Mech, MechSwitch},
+    libpinmame::PinmameMechInfo,
+    pinmame::MechFlag,
+    switches::SwitchStates,
+    text::render_text,
+};
+
+const MECH_WIDTH: u32 = 250;
+const MECH_BAR_WIDTH: u32 = 240;
+const MECH_BAR_HEIGHT: u32 = 10;
+const MECH_RADIUS: i16 = 16;
+const TEXT_HEIGHT: u32 = 14;
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+enum Direction {
+    #[default]
+    Stopped,
+    Forward,
+    Backward,
+}
+
+// Sent from the pinmame callbacks to the ui thread. A mech is shown from the time it is
+// available until the machine is reset.
+pub enum MechEvent {
+    Available(i32, PinmameMechInfo),
+    Updated(i32, PinmameMechInfo),
+}
+
+struct MechState {
+    info: PinmameMechInfo,
+    direction: Direction,
+}
+
+// Shows the mechs with their configured switch windows. The direction is not reported by
+// pinmame so we derive it from the position changes.
+#[derive(Default)]
+pub struct MechPanel {
+    // the custom mechs from the machine definition, in mech number order
+    configs: Vec<Mech>,
+    // by mech number, these don't have to be consecutive
+    mechs: BTreeMap<i32, MechState>,
+}
+
+impl MechPanel {
+    pub fn new(configs: Vec<Mech>) -> MechPanel {
+        MechPanel {
+            configs,
+            ..Default::default()
+        }
+    }
+
+    pub fn handle(&mut self, event: MechEvent) {
+        match event {
+            MechEvent::Available(mech_no, info) => {
+                self.mechs.insert(
+                    mech_no,
+                    MechState {
+                        info,
+                        direction: Direction::Stopped,
+                    },
+                );
+            }
+            MechEvent::Updated(mech_no, info) => match self.mechs.get_mut(&mech_no) {
+                Some(state) => {
+                    let mut delta = info.pos - state.info.pos;
+                    // wrapping around on a rotating mech
+                    if is_circular(&info) && delta.abs() > info.steps / 2 {
+                        delta = -delta;
+                    }
+                    state.direction = match delta {
+                        0 if info.speed == 0 => Direction::Stopped,
+                        0 => state.direction,
+                        d if d > 0 => Direction::Forward,
+                        _ => Direction::Backward,
+                    };
+                    state.info = info;
+                }
+                // eg a late update from before a reset
+                None => debug!("Ignoring update for mech {} that is not available", mech_no),
+            },
+        }
+    }
+
+    // The mechs are gone after a reset, until pinmame makes them available again
+    pub fn clear(&mut self) {
+        self.mechs.clear();
+    }
+
+    pub fn render(
+        &self,
+        at_x: u32,
+        at_y: u32,
+        switch_states: &SwitchStates,
+        canvas: &mut sdl2::render::Canvas<sdl2::video::Window>,
+        font: &sdl2::ttf::Font<'_, '_>,
+    ) -> Result<(), String> {
+        for (index, (mech_no, state)) in self.mechs.iter().enumerate() {
+            let mech = &state.info;
+            let x = at_x + index as u32 * MECH_WIDTH;
+            // custom mechs are installed with their index as mech number
+            let config = usize::try_from(*mech_no)
+                .ok()
+                .and_then(|mech_no| self.configs.get(mech_no));
+            let name = config
+                .and_then(|config| config.name.clone())
+                .unwrap_or_else(|| format!("Mech {}", mech_no));
+            let direction = match state.direction {
+                Direction::Forward => ">",
+                Direction::Backward => "<",
+                Direction::Stopped => "-",
+            };
+            let label = format!(
+                "{} {}/{} speed {} {}",
+                name, mech.pos, mech.steps, mech.speed, direction
+            );
+            render_text(
+                x as i32,
+                at_y as i32,
+                &label,
+                Color::RGB(150, 150, 150),
+                canvas,
+                font,
+            )?;
+            let windows = config
+                .map(|config| config.switches.as_slice())
+                .unwrap_or(&[]);
+            if is_circular(mech) {
+                render_circular(x, at_y + TEXT_HEIGHT, mech, windows, switch_states, canvas)?;
+            } else {
+                render_linear(x, at_y + TEXT_HEIGHT, mech, windows, switch_states, canvas)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+// mechs that neither stop nor reverse at the end keep on turning
+fn is_circular(mech: &PinmameMechInfo) -> bool {
+    !MechFlag::StopEnd.is_set_in(mech.type_) && !MechFlag::Reverse.is_set_in(mech.type_)
+}
+
+fn window_color(switch: u32, switch_states: &SwitchStates) -> Color {
+    if switch_states.is_closed(switch) {
+        Color::RGB(250, 250, 100)
+    } else {
+        Color::RGB(90, 90, 60)
+    }
+}
+
+fn render_linear(
+    at_x: u32,
+    at_y: u32,
+    mech: &PinmameMechInfo,
+    windows: &[MechSwitch],
+    switch_states: &SwitchStates,
+    canvas: &mut sdl2::render::Canvas<sdl2::video::Window>,
+) -> Result<(), String> {
+    let steps = mech.steps.max(1) as u32;
+    let scale = |pos: u32| pos.min(steps) * MECH_BAR_WIDTH / steps;
+
+    canvas.set_draw_color(Color::RGB(50, 150, 50));
+    canvas.fill_rect(Rect::new(
+        at_x as i32,
+        at_y as i32,
+        scale(mech.pos.max(0) as u32),
+        MECH_BAR_HEIGHT,
+    ))?;
+    // the switch windows as marks below the bar
+    for window in windows {
+        let start = scale(window.start);
+        let width = scale(window.end).saturating_sub(start).max(1);
+        canvas.set_draw_color(window_color(window.switch, switch_states));
+        canvas.fill_rect(Rect::new(
+            (at_x + start) as i32,
+            (at_y + MECH_BAR_HEIGHT + 1) as i32,
+            width,
+            3,
+        ))?;
+    }
+    canvas.set_draw_color(Color::RGB(150, 250, 150));
+    canvas.draw_rect(Rect::new(
+        at_x as i32,
+        at_y as i32,
+        MECH_BAR_WIDTH,
+        MECH_BAR_HEIGHT,
+    ))?;
+    Ok(())
+}
+
+fn render_circular(
+    at_x: u32,
+    at_y: u32,
+    mech: &PinmameMechInfo,
+    windows: &[MechSwitch],
+    switch_states: &SwitchStates,
+    canvas: &mut sdl2::render::Canvas<sdl2::video::Window>,
+) -> Result<(), String> {
+    let steps = mech.steps.max(1) as f32;
+    let center_x = (at_x as i16) + MECH_RADIUS;
+    let center_y = (at_y as i16) + MECH_RADIUS;
+    // degrees clockwise starting at the top
+    let angle = |pos: u32| (pos as f32 / steps * 360.0 - 90.0) as i16;
+
+    canvas.circle(center_x, center_y, MECH_RADIUS, Color::RGB(150, 250, 150))?;
+    for window in windows {
+        canvas.arc(
+            center_x,
+            center_y,
+            MECH_RADIUS - 3,
+            angle(window.start),
+            angle(window.end),
+            window_color(window.switch, switch_states),
+        )?;
+    }
+    let radians = (mech.pos as f32 / steps * 360.0 - 90.0).to_radians();
+    canvas.line(
+        center_x,
+        center_y,
+        center_x + (radians.cos() * MECH_RADIUS as f32) as i16,
+        center_y + (radians.sin() * MECH_RADIUS as f32) as i16,
+        Color::RGB(50, 250, 50),
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info(pos: i32) -> PinmameMechInfo {
+        PinmameMechInfo {
+            type_: crate::libpinmame::PINMAME_MECH_FLAGS::from(MechFlag::Reverse) as i32,
+            length: 240,
+            steps: 240,
+            pos,
+            speed: 1,
+        }
+    }
+
+    #[test]
+    fn updates_available_mechs_until_cleared() {
+        let mut panel = MechPanel::default();
+        panel.handle(MechEvent::Updated(0, info(10)));
+        assert!(panel.mechs.is_empty());
+
+        panel.handle(MechEvent::Available(0, info(10)));
+        panel.handle(MechEvent::Updated(0, info(12)));
+        assert_eq!(panel.mechs[&0].info.pos, 12);
+        assert_eq!(panel.mechs[&0].direction, Direction::Forward);
+
+        panel.clear();
+        panel.handle(MechEvent::Updated(0, info(14)));
+        assert!(panel.mechs.is_empty());
+    }
+}
@@ -0,0 +1,261 @@
+use std::{
+    path::Path,
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant},
+};
+
+use log::info;
+use sdl2::{pixels::Color, rect::Rect};
+
+use crate::{
+    libpinmame::PinmameNVRAMState,
+    pinmame::{self, PinmameStatus},
+    text::render_text,
+};
+
+const BYTES_PER_ROW: usize = 16;
+const ROWS: usize = 18;
+const ROW_HEIGHT: u32 = 15;
+const OFFSET_WIDTH: i32 = 48;
+const HEX_WIDTH: i32 = 18;
+// a change stays highlighted for this many frames, fading out, so a byte that changed once
+// is still visible a second later
+const HIGHLIGHT_FRAMES: u8 = 60;
+// how long to wait for the game to report its nvram size
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+pub const NVRAM_WIDTH: u32 = OFFSET_WIDTH as u32 + HEX_WIDTH as u32 * BYTES_PER_ROW as u32 + 4;
+pub const NVRAM_HEIGHT: u32 = ROWS as u32 * ROW_HEIGHT + ROW_HEIGHT + 4;
+
+// The nvram as last reported by pinmame, shown as a hex grid
+#[derive(Default)]
+pub struct NvramView {
+    bytes: Vec<u8>,
+    // frames left to highlight each byte
+    highlight: Vec<u8>,
+    // first row shown
+    scroll: usize,
+}
+
+impl NvramView {
+    // Call once per frame, the first call reads the whole nvram
+    pub fn update(&mut self) {
+        for highlight in self.highlight.iter_mut() {
+            *highlight = highlight.saturating_sub(1);
+        }
+        if self.bytes.is_empty() {
+            let states = pinmame::get_nvram();
+            self.bytes = vec![0; states.len()];
+            self.highlight = vec![0; states.len()];
+            self.apply(&states, false);
+        } else {
+            self.apply(&pinmame::get_changed_nvram(), true);
+        }
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn scroll(&mut self, rows: i32) {
+        let max_scroll = self
+            .bytes
+            .len()
+            .div_ceil(BYTES_PER_ROW)
+            .saturating_sub(ROWS);
+        self.scroll = (self.scroll as i64 + rows as i64).clamp(0, max_scroll as i64) as usize;
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        if self.bytes.is_empty() {
+            return Err("No nvram available".to_string());
+        }
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+        }
+        std::fs::write(path, &self.bytes).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    fn apply(&mut self, states: &[PinmameNVRAMState], highlight: bool) {
+        for state in states {
+            let Some(byte) = self.bytes.get_mut(state.nvramNo as usize) else {
+                continue;
+            };
+            *byte = state.currStat;
+            if highlight {
+                self.highlight[state.nvramNo as usize] = HIGHLIGHT_FRAMES;
+            }
+        }
+    }
+}
+
+// Set while the game is booted to read its nvram size, the state callback should not treat
+// that game stopping as the end of the session
+static PROBING: AtomicBool = AtomicBool::new(false);
+
+pub fn is_probing() -> bool {
+    PROBING.load(Ordering::SeqCst)
+}
+
+// pinmame has no api to write the nvram, it loads `nvram/<rom>.nv` when the game starts,
+// so we put the dump there before booting. The nvram size is only known while the game runs,
+// so the game is booted once to check the dump belongs to it. Stopping that game writes
+// `<rom>.nv`, so the backup is taken before.
+pub fn restore(dump: &Path, pinmame_path: &Path, rom: &str) -> Result<(), String> {
+    let bytes = std::fs::read(dump).map_err(|e| format!("{}: {}", dump.display(), e))?;
+    let target = pinmame_path.join("nvram").join(format!("{}.nv", rom));
+    if let Some(parent) = target.parent() {
+        std::fs::create_dir_all(parent).map_err(|e| e.to_string())?;
+    }
+    let backup = if target.exists() {
+        let timestamp = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        let backup = target.with_file_name(format!("{}-{}.bak", rom, timestamp));
+        std::fs::copy(&target, &backup).map_err(|e| {
+            format!(
+                "Could not back up {} to {}: {}",
+                target.display(),
+                backup.display(),
+                e
+            )
+        })?;
+        info!("Backed up {} to {}", target.display(), backup.display());
+        Some(backup)
+    } else {
+        None
+    };
+
+    let size = probe_nvram_size(rom)?;
+    if bytes.len() != size {
+        // undo what the probe wrote
+        if let Some(backup) = &backup {
+            std::fs::copy(backup, &target).map_err(|e| {
+                format!(
+                    "Could not put back {} from {}: {}",
+                    target.display(),
+                    backup.display(),
+                    e
+                )
+            })?;
+        }
+        return Err(format!(
+            "{} holds {} bytes but {} has {} bytes of nvram",
+            dump.display(),
+            bytes.len(),
+            rom,
+            size
+        ));
+    }
+    std::fs::write(&target, &bytes).map_err(|e| format!("{}: {}", target.display(), e))?;
+    info!("Replaced {} with {}", target.display(), dump.display());
+    Ok(())
+}
+
+fn probe_nvram_size(rom: &str) -> Result<usize, String> {
+    PROBING.store(true, Ordering::SeqCst);
+    let size = boot_for_nvram_size(rom);
+    PROBING.store(false, Ordering::SeqCst);
+    size
+}
+
+fn boot_for_nvram_size(rom: &str) -> Result<usize, String> {
+    if pinmame::run(rom) != PinmameStatus::Ok {
+        return Err(format!("Could not start {} to check the nvram size", rom));
+    }
+    let started = Instant::now();
+    let mut size = pinmame::get_max_nvram();
+    while size <= 0 && started.elapsed() < PROBE_TIMEOUT {
+        std::thread::sleep(Duration::from_millis(10));
+        size = pinmame::get_max_nvram();
+    }
+    pinmame::stop();
+    // the game has to be gone before it is started again
+    let stopped = Instant::now();
+    while pinmame::is_running() && stopped.elapsed() < PROBE_TIMEOUT {
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    if pinmame::is_running() {
+        return Err(format!(
+            "{} did not stop after checking the nvram size",
+            rom
+        ));
+    }
+    if size <= 0 {
+        return Err(format!("{} reports no nvram", rom));
+    }
+    Ok(size as usize)
+}
+
+pub fn render_nvram(
+    at_x: u32,
+    at_y: u32,
+    nvram: &NvramView,
+    canvas: &mut sdl2::render::Canvas<sdl2::video::Window>,
+    font: &sdl2::ttf::Font<'_, '_>,
+) -> Result<(), String> {
+    canvas.set_draw_color(Color::RGB(20, 20, 20));
+    canvas.fill_rect(Rect::new(
+        at_x as i32,
+        at_y as i32,
+        NVRAM_WIDTH,
+        NVRAM_HEIGHT,
+    ))?;
+
+    let title = format!("NVRAM: {} bytes", nvram.bytes.len());
+    render_text(
+        at_x as i32 + 2,
+        at_y as i32,
+        &title,
+        Color::RGB(150, 150, 150),
+        canvas,
+        font,
+    )?;
+
+    let offset_color = Color::RGB(100, 100, 160);
+    for line in 0..ROWS {
+        let start = (nvram.scroll + line) * BYTES_PER_ROW;
+        if start >= nvram.bytes.len() {
+            break;
+        }
+        let end = (start + BYTES_PER_ROW).min(nvram.bytes.len());
+        let y = (at_y + ROW_HEIGHT * (line as u32 + 1)) as i32;
+        let offset = format!("{:04x}", start);
+        render_text(at_x as i32 + 2, y, &offset, offset_color, canvas, font)?;
+
+        let mut x = at_x as i32 + 2 + OFFSET_WIDTH;
+        for address in start..end {
+            // changed bytes start out yellow and fade to the normal color
+            let fade = nvram.highlight[address] as u32;
+            let blend = |from: u32, to: u32| {
+                ((from * (HIGHLIGHT_FRAMES as u32 - fade) + to * fade) / HIGHLIGHT_FRAMES as u32)
+                    as u8
+            };
+            let color = Color::RGB(blend(200, 255), blend(200, 230), blend(200, 0));
+            let hex = format!("{:02x}", nvram.bytes[address]);
+            render_text(x, y, &hex, color, canvas, font)?;
+            x += HEX_WIDTH;
+        }
+    }
+    Ok(())
+}
+
+pub fn is_over_nvram(x: i32, y: i32, at_x: u32, at_y: u32) -> bool {
+    Rect::new(at_x as i32, at_y as i32, NVRAM_WIDTH, NVRAM_HEIGHT).contains_point((x, y))
+}
+
+// The address of the byte under the mouse, relative to the top left of the grid
+pub fn address_for_mouse(x: i32, y: i32, nvram: &NvramView) -> Option<usize> {
+    let x = x - 2 - OFFSET_WIDTH;
+    let y = y - ROW_HEIGHT as i32;
+    if x < 0 || y < 0 {
+        return None;
+    }
+    let column = (x / HEX_WIDTH) as usize;
+    let line = (y / ROW_HEIGHT as i32) as usize;
+    if column >= BYTES_PER_ROW || line >= ROWS {
+        return None;
+    }
+    let address = (nvram.scroll + line) * BYTES_PER_ROW + column;
+    (address < nvram.bytes.len()).then_some(address)
+}
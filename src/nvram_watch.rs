@@ -0,0 +1,212 @@
+use sdl2::{pixels::Color, rect::Rect};
+
+use crate::text::render_text;
+
+const ROWS: usize = 10;
+const ROW_HEIGHT: u32 = 15;
+// longer changes are cut off in the list
+const SHOWN_BYTES: usize = 6;
+pub const WATCH_WIDTH: u32 = 270;
+pub const WATCH_HEIGHT: u32 = (ROWS as u32 + 2) * ROW_HEIGHT + 4;
+
+// A run of consecutive nvram bytes that differ from the baseline
+#[derive(Debug, Clone)]
+pub struct NvramChange {
+    pub address: usize,
+    pub old: Vec<u8>,
+    pub new: Vec<u8>,
+}
+
+// Finds where audits and settings live: mark a baseline, do something on the machine and
+// see which bytes changed. A change can be saved as a named field in the machine definition.
+#[derive(Default)]
+pub struct NvramWatch {
+    baseline: Option<Vec<u8>>,
+    // the change being named and the name typed so far
+    naming: Option<(NvramChange, String)>,
+}
+
+impl NvramWatch {
+    pub fn is_active(&self) -> bool {
+        self.baseline.is_some()
+    }
+
+    pub fn mark(&mut self, bytes: &[u8]) {
+        self.baseline = Some(bytes.to_vec());
+        self.naming = None;
+    }
+
+    pub fn stop(&mut self) {
+        self.baseline = None;
+        self.naming = None;
+    }
+
+    pub fn changes(&self, bytes: &[u8]) -> Vec<NvramChange> {
+        let Some(baseline) = &self.baseline else {
+            return vec![];
+        };
+        let mut changes: Vec<NvramChange> = Vec::new();
+        for (address, (old, new)) in baseline.iter().zip(bytes).enumerate() {
+            if old == new {
+                continue;
+            }
+            match changes.last_mut() {
+                Some(change) if change.address + change.new.len() == address => {
+                    change.old.push(*old);
+                    change.new.push(*new);
+                }
+                _ => changes.push(NvramChange {
+                    address,
+                    old: vec![*old],
+                    new: vec![*new],
+                }),
+            }
+        }
+        changes
+    }
+
+    pub fn is_naming(&self) -> bool {
+        self.naming.is_some()
+    }
+
+    pub fn start_naming(&mut self, change: NvramChange) {
+        self.naming = Some((change, String::new()));
+    }
+
+    pub fn type_text(&mut self, text: &str) {
+        if let Some((_, name)) = self.naming.as_mut() {
+            name.push_str(text);
+        }
+    }
+
+    pub fn backspace(&mut self) {
+        if let Some((_, name)) = self.naming.as_mut() {
+            name.pop();
+        }
+    }
+
+    pub fn cancel_naming(&mut self) {
+        self.naming = None;
+    }
+
+    // The change with its name, unless no name was typed
+    pub fn finish_naming(&mut self) -> Option<(NvramChange, String)> {
+        let (change, name) = self.naming.take()?;
+        let name = name.trim().to_string();
+        (!name.is_empty()).then_some((change, name))
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    let mut text = bytes
+        .iter()
+        .take(SHOWN_BYTES)
+        .map(|byte| format!("{:02x}", byte))
+        .collect::<Vec<_>>()
+        .join(" ");
+    if bytes.len() > SHOWN_BYTES {
+        text.push_str(" ..");
+    }
+    text
+}
+
+pub fn render_watch(
+    at_x: u32,
+    at_y: u32,
+    watch: &NvramWatch,
+    bytes: &[u8],
+    canvas: &mut sdl2::render::Canvas<sdl2::video::Window>,
+    font: &sdl2::ttf::Font<'_, '_>,
+) -> Result<(), String> {
+    canvas.set_draw_color(Color::RGB(20, 20, 20));
+    canvas.fill_rect(Rect::new(
+        at_x as i32,
+        at_y as i32,
+        WATCH_WIDTH,
+        WATCH_HEIGHT,
+    ))?;
+
+    let changes = watch.changes(bytes);
+    let title = format!("NVRAM changes since baseline: {}", changes.len());
+    render_text(
+        at_x as i32 + 2,
+        at_y as i32,
+        &title,
+        Color::RGB(150, 150, 150),
+        canvas,
+        font,
+    )?;
+    for (line, change) in changes.iter().take(ROWS).enumerate() {
+        let y = (at_y + ROW_HEIGHT * (line as u32 + 1)) as i32;
+        let text = format!(
+            "0x{:04x} [{}] {} -> {}",
+            change.address,
+            change.new.len(),
+            hex(&change.old),
+            hex(&change.new)
+        );
+        render_text(
+            at_x as i32 + 2,
+            y,
+            &text,
+            Color::RGB(200, 200, 200),
+            canvas,
+            font,
+        )?;
+    }
+
+    let last_y = (at_y + ROW_HEIGHT * (ROWS as u32 + 1)) as i32;
+    let (prompt, color) = match &watch.naming {
+        Some((change, name)) => (
+            format!("Name for 0x{:04x}: {}_", change.address, name),
+            Color::RGB(250, 250, 100),
+        ),
+        None if changes.len() > ROWS => (
+            format!("{} more, click a change to name it", changes.len() - ROWS),
+            Color::RGB(150, 150, 150),
+        ),
+        None => (
+            "Click a change to name it".to_string(),
+            Color::RGB(150, 150, 150),
+        ),
+    };
+    render_text(at_x as i32 + 2, last_y, &prompt, color, canvas, font)?;
+    Ok(())
+}
+
+// The change under the mouse, relative to the top left of the panel
+pub fn change_for_mouse(x: i32, y: i32, watch: &NvramWatch, bytes: &[u8]) -> Option<NvramChange> {
+    if x < 0 || x >= WATCH_WIDTH as i32 || y < ROW_HEIGHT as i32 {
+        return None;
+    }
+    let line = (y / ROW_HEIGHT as i32) as usize - 1;
+    if line >= ROWS {
+        return None;
+    }
+    watch.changes(bytes).into_iter().nth(line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn groups_consecutive_changes_into_runs() {
+        let mut watch = NvramWatch::default();
+        assert!(watch.changes(&[0; 8]).is_empty());
+
+        watch.mark(&[0, 1, 2, 3, 4, 5, 6, 7]);
+        let changes = watch.changes(&[0, 9, 9, 3, 4, 5, 9, 7]);
+        assert_eq!(changes.len(), 2);
+        assert_eq!(changes[0].address, 1);
+        assert_eq!(changes[0].old, [1, 2]);
+        assert_eq!(changes[0].new, [9, 9]);
+        assert_eq!(changes[1].address, 6);
+        assert_eq!(changes[1].old, [6]);
+        assert_eq!(changes[1].new, [9]);
+
+        // a new baseline starts over
+        watch.mark(&[0, 9, 9, 3, 4, 5, 9, 7]);
+        assert!(watch.changes(&[0, 9, 9, 3, 4, 5, 9, 7]).is_empty());
+    }
+}
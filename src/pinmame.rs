@@ -1,22 +1,35 @@
 use std::ffi::{CStr, CString, c_void};
 
 use log::{debug, error, info, trace, warn};
+use serde::{Deserialize, Serialize};
 
 use crate::libpinmame::{
     PINMAME_DMD_MODE, PINMAME_DMD_MODE_PINMAME_DMD_MODE_BRIGHTNESS,
     PINMAME_DMD_MODE_PINMAME_DMD_MODE_RAW, PINMAME_LOG_LEVEL,
     PINMAME_LOG_LEVEL_PINMAME_LOG_LEVEL_DEBUG, PINMAME_LOG_LEVEL_PINMAME_LOG_LEVEL_ERROR,
-    PINMAME_LOG_LEVEL_PINMAME_LOG_LEVEL_INFO, PINMAME_STATUS,
+    PINMAME_LOG_LEVEL_PINMAME_LOG_LEVEL_INFO, PINMAME_MECH_FLAGS,
+    PINMAME_MECH_FLAGS_PINMAME_MECH_FLAGS_CIRCLE, PINMAME_MECH_FLAGS_PINMAME_MECH_FLAGS_FAST,
+    PINMAME_MECH_FLAGS_PINMAME_MECH_FLAGS_FOURSTEPSOL,
+    PINMAME_MECH_FLAGS_PINMAME_MECH_FLAGS_LENGTHSW, PINMAME_MECH_FLAGS_PINMAME_MECH_FLAGS_LINEAR,
+    PINMAME_MECH_FLAGS_PINMAME_MECH_FLAGS_NONLINEAR,
+    PINMAME_MECH_FLAGS_PINMAME_MECH_FLAGS_ONEDIRSOL, PINMAME_MECH_FLAGS_PINMAME_MECH_FLAGS_ONESOL,
+    PINMAME_MECH_FLAGS_PINMAME_MECH_FLAGS_REVERSE, PINMAME_MECH_FLAGS_PINMAME_MECH_FLAGS_STOPEND,
+    PINMAME_MECH_FLAGS_PINMAME_MECH_FLAGS_TWODIRSOL,
+    PINMAME_MECH_FLAGS_PINMAME_MECH_FLAGS_TWOSTEPSOL, PINMAME_STATUS,
     PINMAME_STATUS_PINMAME_STATUS_CONFIG_NOT_SET,
     PINMAME_STATUS_PINMAME_STATUS_EMULATOR_NOT_RUNNING,
     PINMAME_STATUS_PINMAME_STATUS_GAME_ALREADY_RUNNING,
     PINMAME_STATUS_PINMAME_STATUS_GAME_NOT_FOUND,
     PINMAME_STATUS_PINMAME_STATUS_MECH_HANDLE_MECHANICS,
     PINMAME_STATUS_PINMAME_STATUS_MECH_NO_INVALID, PINMAME_STATUS_PINMAME_STATUS_OK, PinmameConfig,
-    PinmameGame, PinmameGetChangedLamps, PinmameGetChangedSolenoids, PinmameGetGame,
-    PinmameGetGames, PinmameGetMaxLamps, PinmameGetMaxSolenoids, PinmameGetSwitch, PinmameIsPaused,
-    PinmameIsRunning, PinmameLampState, PinmamePause, PinmameReset, PinmameRun, PinmameSetConfig,
-    PinmameSetDmdMode, PinmameSetHandleKeyboard, PinmameSetHandleMechanics, PinmameSetSwitch,
+    PinmameGIState, PinmameGame, PinmameGetChangedGIs, PinmameGetChangedLEDs,
+    PinmameGetChangedLamps, PinmameGetChangedNVRAM, PinmameGetChangedSolenoids, PinmameGetGame,
+    PinmameGetGames, PinmameGetHandleMechanics, PinmameGetMaxGIs, PinmameGetMaxLEDs,
+    PinmameGetMaxLamps, PinmameGetMaxMechs, PinmameGetMaxNVRAM, PinmameGetMaxSolenoids,
+    PinmameGetMech, PinmameGetNVRAM, PinmameGetSwitch, PinmameIsPaused, PinmameIsRunning,
+    PinmameLEDState, PinmameLampState, PinmameMechConfig, PinmameMechSwitchConfig,
+    PinmameNVRAMState, PinmamePause, PinmameReset, PinmameRun, PinmameSetConfig, PinmameSetDmdMode,
+    PinmameSetHandleKeyboard, PinmameSetHandleMechanics, PinmameSetMech, PinmameSetSwitch,
     PinmameSetSwitches, PinmameSetUserData, PinmameSolenoidState, PinmameStop, PinmameSwitchState,
 };
 
@@ -111,6 +124,10 @@ pub fn set_handle_mechanics(handle: bool) {
     unsafe { PinmameSetHandleMechanics(handle as i32) }
 }
 
+pub fn get_handle_mechanics() -> bool {
+    unsafe { PinmameGetHandleMechanics() != 0 }
+}
+
 pub fn set_dmd_mode(dmd_mode: DmdMode) {
     unsafe { PinmameSetDmdMode(dmd_mode.into()) }
 }
@@ -239,6 +256,89 @@ pub fn get_changed_solenoids() -> Vec<PinmameSolenoidState> {
     states.to_vec()
 }
 
+pub fn get_max_leds() -> i32 {
+    unsafe { PinmameGetMaxLEDs() }
+}
+
+// The state of an led digit is a bitmask of its lit segments
+pub fn get_changed_leds() -> Vec<PinmameLEDState> {
+    let max_leds = get_max_leds();
+    if max_leds <= 0 {
+        return vec![];
+    }
+    let mut leds_changed = vec![
+        PinmameLEDState {
+            ledNo: 0,
+            chgSeg: 0,
+            state: 0
+        };
+        max_leds as usize
+    ];
+    // the masks select leds 0-63 and 64-127, we want all of them
+    let num = unsafe { PinmameGetChangedLEDs(u64::MAX, u64::MAX, leds_changed.as_mut_ptr()) };
+    if num <= 0 {
+        return vec![];
+    }
+    leds_changed.truncate(num as usize);
+    leds_changed
+}
+
+pub fn get_max_nvram() -> i32 {
+    unsafe { PinmameGetMaxNVRAM() }
+}
+
+fn read_nvram(
+    read: unsafe extern "C" fn(*mut PinmameNVRAMState) -> std::os::raw::c_int,
+) -> Vec<PinmameNVRAMState> {
+    let max_nvram = get_max_nvram();
+    if max_nvram <= 0 {
+        return vec![];
+    }
+    let mut states = vec![
+        PinmameNVRAMState {
+            nvramNo: 0,
+            oldStat: 0,
+            currStat: 0
+        };
+        max_nvram as usize
+    ];
+    let num = unsafe { read(states.as_mut_ptr()) };
+    if num <= 0 {
+        return vec![];
+    }
+    states.truncate(num as usize);
+    states
+}
+
+// All nvram bytes, by address
+pub fn get_nvram() -> Vec<PinmameNVRAMState> {
+    read_nvram(PinmameGetNVRAM)
+}
+
+// The bytes that changed since the last call
+pub fn get_changed_nvram() -> Vec<PinmameNVRAMState> {
+    read_nvram(PinmameGetChangedNVRAM)
+}
+
+pub fn get_max_gis() -> i32 {
+    unsafe { PinmameGetMaxGIs() }
+}
+
+pub fn get_changed_gis() -> Vec<PinmameGIState> {
+    // -1 when the emulator is not running
+    let max_gis = get_max_gis().max(0) as usize;
+    if max_gis == 0 {
+        return vec![];
+    }
+    let mut gis_changed = vec![PinmameGIState { giNo: 0, state: 0 }; max_gis];
+    let num = unsafe { PinmameGetChangedGIs(gis_changed.as_mut_ptr()) };
+    if num <= 0 {
+        return vec![];
+    }
+    gis_changed.truncate(num as usize);
+    gis_changed
+}
+
 struct GameUserData {
     game: Option<Game>,
 }
@@ -277,15 +377,6 @@ pub extern "C" fn pinmame_on_solenoid_updated_callback(
     }
 }
 
-//TODO make private
-pub unsafe extern "C" fn pinmame_on_console_data_updated_callback(
-    _data: *mut ::std::os::raw::c_void,
-    size: i32,
-    _user_data: *mut ::std::os::raw::c_void,
-) {
-    info!("OnConsoleDataUpdated: size={}", size);
-}
-
 // see https://github.com/rust-lang/rust-bindgen/issues/2631
 #[cfg(not(all(target_os = "macos", target_arch = "aarch64")))]
 type VaListType = *mut crate::libpinmame::__va_list_tag;
@@ -322,3 +413,200 @@ fn on_log_message(log_level: PINMAME_LOG_LEVEL, str: String) {
         _ => warn!("Unknown log level: {}", log_level),
     }
 }
+
+// in machine definitions by their lowercase names, eg "nonlinear"
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MechFlag {
+    Linear,
+    NonLinear,
+    Circle,
+    StopEnd,
+    Reverse,
+    OneSol,
+    OneDirSol,
+    TwoDirSol,
+    TwoStepSol,
+    FourStepSol,
+    Fast,
+    LengthSw,
+}
+
+impl From<MechFlag> for PINMAME_MECH_FLAGS {
+    fn from(flag: MechFlag) -> Self {
+        match flag {
+            MechFlag::Linear => PINMAME_MECH_FLAGS_PINMAME_MECH_FLAGS_LINEAR,
+            MechFlag::NonLinear => PINMAME_MECH_FLAGS_PINMAME_MECH_FLAGS_NONLINEAR,
+            MechFlag::Circle => PINMAME_MECH_FLAGS_PINMAME_MECH_FLAGS_CIRCLE,
+            MechFlag::StopEnd => PINMAME_MECH_FLAGS_PINMAME_MECH_FLAGS_STOPEND,
+            MechFlag::Reverse => PINMAME_MECH_FLAGS_PINMAME_MECH_FLAGS_REVERSE,
+            MechFlag::OneSol => PINMAME_MECH_FLAGS_PINMAME_MECH_FLAGS_ONESOL,
+            MechFlag::OneDirSol => PINMAME_MECH_FLAGS_PINMAME_MECH_FLAGS_ONEDIRSOL,
+            MechFlag::TwoDirSol => PINMAME_MECH_FLAGS_PINMAME_MECH_FLAGS_TWODIRSOL,
+            MechFlag::TwoStepSol => PINMAME_MECH_FLAGS_PINMAME_MECH_FLAGS_TWOSTEPSOL,
+            MechFlag::FourStepSol => PINMAME_MECH_FLAGS_PINMAME_MECH_FLAGS_FOURSTEPSOL,
+            MechFlag::Fast => PINMAME_MECH_FLAGS_PINMAME_MECH_FLAGS_FAST,
+            MechFlag::LengthSw => PINMAME_MECH_FLAGS_PINMAME_MECH_FLAGS_LENGTHSW,
+        }
+    }
+}
+
+impl MechFlag {
+    // Linear, Circle and OneSol are 0, they are the absence of the other flags
+    pub fn is_set_in(self, mech_type: i32) -> bool {
+        mech_type as PINMAME_MECH_FLAGS & PINMAME_MECH_FLAGS::from(self) != 0
+    }
+}
+
+const MAX_MECH_SWITCHES: usize = 20;
+
+// A validated PinmameMechConfig, create one with `MechConfig::builder`
+#[derive(Debug, Copy, Clone)]
+pub struct MechConfig {
+    config: PinmameMechConfig,
+}
+
+impl MechConfig {
+    pub fn builder(sol1: i32, length: i32, steps: i32) -> MechConfigBuilder {
+        MechConfigBuilder {
+            config: PinmameMechConfig {
+                type_: 0,
+                sol1,
+                sol2: 0,
+                length,
+                steps,
+                initialPos: 0,
+                acc: 0,
+                ret: 0,
+                // unused switch slots stay at switch 0
+                sw: [PinmameMechSwitchConfig {
+                    swNo: 0,
+                    startPos: 0,
+                    endPos: 0,
+                    pulse: 0,
+                }; MAX_MECH_SWITCHES],
+            },
+            switches: 0,
+            error: None,
+        }
+    }
+}
+
+pub struct MechConfigBuilder {
+    config: PinmameMechConfig,
+    switches: usize,
+    error: Option<String>,
+}
+
+impl MechConfigBuilder {
+    pub fn flag(mut self, flag: MechFlag) -> Self {
+        self.config.type_ |= PINMAME_MECH_FLAGS::from(flag) as i32;
+        self
+    }
+
+    pub fn sol2(mut self, sol2: i32) -> Self {
+        self.config.sol2 = sol2;
+        self
+    }
+
+    pub fn initial_pos(mut self, initial_pos: i32) -> Self {
+        self.config.initialPos = initial_pos;
+        self
+    }
+
+    pub fn acc(mut self, acc: i32) -> Self {
+        self.config.acc = acc;
+        self
+    }
+
+    pub fn ret(mut self, ret: i32) -> Self {
+        self.config.ret = ret;
+        self
+    }
+
+    // The switch is closed while the mech position is between start and end
+    pub fn switch(mut self, sw_no: i32, start_pos: i32, end_pos: i32, pulse: i32) -> Self {
+        if self.switches == MAX_MECH_SWITCHES {
+            self.error = Some(format!(
+                "A mech can have at most {} switches",
+                MAX_MECH_SWITCHES
+            ));
+            return self;
+        }
+        self.config.sw[self.switches] = PinmameMechSwitchConfig {
+            swNo: sw_no,
+            startPos: start_pos,
+            endPos: end_pos,
+            pulse,
+        };
+        self.switches += 1;
+        self
+    }
+
+    pub fn build(self) -> Result<MechConfig, String> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        if self.config.length <= 0 || self.config.steps <= 0 {
+            return Err(format!(
+                "Mech length and steps must be positive, got {} and {}",
+                self.config.length, self.config.steps
+            ));
+        }
+        Ok(MechConfig {
+            config: self.config,
+        })
+    }
+}
+
+fn check_mech_no(mech_no: i32) -> Result<(), PinmameStatus> {
+    if mech_no < 0 || mech_no >= get_max_mechs() {
+        return Err(PinmameStatus::MechNoInvalid);
+    }
+    if get_handle_mechanics() {
+        return Err(PinmameStatus::MechHandleMechanics);
+    }
+    Ok(())
+}
+
+pub fn get_max_mechs() -> i32 {
+    unsafe { PinmameGetMaxMechs() }
+}
+
+// Only works when pinmame is not handling the mechanics itself
+pub fn set_mech(mech_no: i32, config: &MechConfig) -> Result<(), PinmameStatus> {
+    check_mech_no(mech_no)?;
+    let status: PinmameStatus = unsafe { PinmameSetMech(mech_no, &config.config) }.into();
+    if status != PinmameStatus::Ok {
+        return Err(status);
+    }
+    Ok(())
+}
+
+pub fn clear_mech(mech_no: i32) -> Result<(), PinmameStatus> {
+    check_mech_no(mech_no)?;
+    let status: PinmameStatus = unsafe { PinmameSetMech(mech_no, std::ptr::null()) }.into();
+    if status != PinmameStatus::Ok {
+        return Err(status);
+    }
+    Ok(())
+}
+
+// The current position of a custom mech
+pub fn get_mech(mech_no: i32) -> Result<i32, PinmameStatus> {
+    check_mech_no(mech_no)?;
+    Ok(unsafe { PinmameGetMech(mech_no) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mech_wrappers_refuse_negative_mech_numbers() {
+        let config = MechConfig::builder(1, 200, 200).build().unwrap();
+        assert_eq!(set_mech(-1, &config), Err(PinmameStatus::MechNoInvalid));
+        assert_eq!(clear_mech(-1), Err(PinmameStatus::MechNoInvalid));
+        assert_eq!(get_mech(-1), Err(PinmameStatus::MechNoInvalid));
+    }
+}
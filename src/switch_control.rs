@@ -0,0 +1,276 @@
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
+
+use log::debug;
+
+use crate::{libpinmame::PinmameSwitchState, pinmame};
+
+// Real switches bounce a few times before they settle, some roms are picky about that.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Debounce {
+    pub bounces: u32,
+    pub interval: Duration,
+}
+
+impl Default for Debounce {
+    fn default() -> Self {
+        Debounce {
+            bounces: 2,
+            interval: Duration::from_millis(1),
+        }
+    }
+}
+
+// Collects switch changes and hands them to pinmame in a single `set_switches` call per
+// update, so changes made in the same frame are seen by the rom at the same time.
+// A switch changes at most once per update, bounces last at least a frame.
+#[derive(Default)]
+pub struct SwitchController {
+    now: Duration,
+    pending: Vec<(u32, bool)>,
+    // sorted by time
+    scheduled: Vec<(Duration, u32, bool)>,
+    held: HashSet<u32>,
+    // what we last sent to pinmame
+    last: HashMap<u32, bool>,
+    debounce: Option<Debounce>,
+}
+
+impl SwitchController {
+    pub fn debounce(&self) -> Option<Debounce> {
+        self.debounce
+    }
+
+    pub fn set_debounce(&mut self, debounce: Option<Debounce>) {
+        self.debounce = debounce;
+    }
+
+    pub fn set(&mut self, switch: u32, closed: bool) {
+        self.cancel_scheduled(switch);
+        match self.debounce {
+            Some(debounce) if self.last.get(&switch).copied().unwrap_or(false) != closed => {
+                // flip back and forth, ending up in the requested state
+                let mut state = closed;
+                let mut at = self.now;
+                self.pending.push((switch, state));
+                for _ in 0..debounce.bounces * 2 {
+                    state = !state;
+                    at += debounce.interval;
+                    self.schedule(at, switch, state);
+                }
+            }
+            _ => self.pending.push((switch, closed)),
+        }
+    }
+
+    // all changes are applied in the same batch
+    pub fn set_many(&mut self, changes: &[(u32, bool)]) {
+        for (switch, closed) in changes {
+            self.set(*switch, *closed);
+        }
+    }
+
+    // closes the switch and opens it again after the duration, unless it is held
+    pub fn pulse(&mut self, switch: u32, duration: Duration) {
+        self.set(switch, true);
+        if !self.held.contains(&switch) {
+            let at = self.now + duration;
+            self.schedule(at, switch, false);
+        }
+    }
+
+    pub fn hold(&mut self, switch: u32) {
+        self.held.insert(switch);
+        self.set(switch, true);
+    }
+
+    pub fn release(&mut self, switch: u32) {
+        self.held.remove(&switch);
+        self.set(switch, false);
+    }
+
+    pub fn is_held(&self, switch: u32) -> bool {
+        self.held.contains(&switch)
+    }
+
+    // Forgets the changes still to come and the held switches and opens every switch we
+    // closed, as if the machine was switched off
+    pub fn reset(&mut self) {
+        self.pending.clear();
+        self.scheduled.clear();
+        self.held.clear();
+        let mut closed: Vec<u32> = self
+            .last
+            .iter()
+            .filter(|(_, closed)| **closed)
+            .map(|(switch, _)| *switch)
+            .collect();
+        closed.sort();
+        self.pending
+            .extend(closed.into_iter().map(|switch| (switch, false)));
+    }
+
+    // Sends everything that is due at `now` to pinmame
+    pub fn update(&mut self, now: Duration) {
+        let states = self.next_batch(now);
+        if !states.is_empty() {
+            debug!("Setting {} switches", states.len());
+            pinmame::set_switches(&states);
+        }
+    }
+
+    fn next_batch(&mut self, now: Duration) -> Vec<PinmameSwitchState> {
+        self.now = now;
+        let mut states: Vec<PinmameSwitchState> = Vec::new();
+        // of the changes made since the last update only the last one for a switch counts
+        for (switch, closed) in self.pending.drain(..) {
+            match states.iter_mut().find(|state| state.swNo == switch as i32) {
+                Some(state) => state.state = closed as i32,
+                None => states.push(PinmameSwitchState {
+                    swNo: switch as i32,
+                    state: closed as i32,
+                }),
+            }
+        }
+        // Scheduled changes of a switch go out one per update, otherwise a bounce or a short
+        // pulse would be merged away before the rom ever sees it. This stretches them to at
+        // least a frame.
+        let mut index = 0;
+        while index < self.scheduled.len() && self.scheduled[index].0 <= now {
+            let (_, switch, closed) = self.scheduled[index];
+            if states.iter().any(|state| state.swNo == switch as i32) {
+                index += 1;
+                continue;
+            }
+            self.scheduled.remove(index);
+            states.push(PinmameSwitchState {
+                swNo: switch as i32,
+                state: closed as i32,
+            });
+        }
+        for state in &states {
+            self.last.insert(state.swNo as u32, state.state != 0);
+        }
+        states
+    }
+
+    fn schedule(&mut self, at: Duration, switch: u32, closed: bool) {
+        let index = self.scheduled.partition_point(|(other, _, _)| *other <= at);
+        self.scheduled.insert(index, (at, switch, closed));
+    }
+
+    fn cancel_scheduled(&mut self, switch: u32) {
+        self.scheduled.retain(|(_, other, _)| *other != switch);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAME: Duration = Duration::from_millis(16);
+
+    fn batch(controller: &mut SwitchController, frame: u32) -> Vec<(u32, bool)> {
+        controller
+            .next_batch(FRAME * frame)
+            .iter()
+            .map(|state| (state.swNo as u32, state.state != 0))
+            .collect()
+    }
+
+    #[test]
+    fn batches_the_changes_of_a_frame() {
+        let mut controller = SwitchController::default();
+        controller.set(11, true);
+        controller.set_many(&[(12, true), (13, true)]);
+        controller.set(12, false);
+        assert_eq!(
+            batch(&mut controller, 0),
+            vec![(11, true), (12, false), (13, true)]
+        );
+        assert_eq!(batch(&mut controller, 1), vec![]);
+    }
+
+    #[test]
+    fn pulse_releases_after_the_duration() {
+        let mut controller = SwitchController::default();
+        controller.pulse(13, Duration::from_millis(50));
+        assert_eq!(batch(&mut controller, 0), vec![(13, true)]);
+        assert_eq!(batch(&mut controller, 1), vec![]);
+        assert_eq!(batch(&mut controller, 2), vec![]);
+        assert_eq!(batch(&mut controller, 4), vec![(13, false)]);
+    }
+
+    #[test]
+    fn short_pulse_is_not_merged_away() {
+        let mut controller = SwitchController::default();
+        batch(&mut controller, 0);
+        controller.pulse(13, Duration::from_millis(1));
+        assert_eq!(batch(&mut controller, 1), vec![(13, true)]);
+        assert_eq!(batch(&mut controller, 2), vec![(13, false)]);
+    }
+
+    #[test]
+    fn held_switch_is_not_released_by_a_pulse() {
+        let mut controller = SwitchController::default();
+        controller.hold(13);
+        controller.pulse(13, Duration::from_millis(1));
+        assert_eq!(batch(&mut controller, 0), vec![(13, true)]);
+        assert_eq!(batch(&mut controller, 1), vec![]);
+        controller.release(13);
+        assert_eq!(batch(&mut controller, 2), vec![(13, false)]);
+    }
+
+    #[test]
+    fn bounces_go_out_one_per_frame() {
+        let mut controller = SwitchController::default();
+        controller.set_debounce(Some(Debounce::default()));
+        controller.set(15, true);
+        controller.set(16, true);
+        let frames: Vec<Vec<(u32, bool)>> =
+            (0..6).map(|frame| batch(&mut controller, frame)).collect();
+        assert_eq!(
+            frames,
+            vec![
+                vec![(15, true), (16, true)],
+                vec![(15, false), (16, false)],
+                vec![(15, true), (16, true)],
+                vec![(15, false), (16, false)],
+                vec![(15, true), (16, true)],
+                vec![],
+            ]
+        );
+    }
+
+    #[test]
+    fn no_bounce_without_a_change() {
+        let mut controller = SwitchController::default();
+        controller.set(15, true);
+        batch(&mut controller, 0);
+        controller.set_debounce(Some(Debounce::default()));
+        controller.set(15, true);
+        assert_eq!(batch(&mut controller, 1), vec![(15, true)]);
+        assert_eq!(batch(&mut controller, 2), vec![]);
+    }
+
+    #[test]
+    fn reset_drops_pulses_and_held_switches() {
+        let mut controller = SwitchController::default();
+        controller.hold(11);
+        controller.pulse(13, Duration::from_millis(50));
+        controller.set(14, true);
+        controller.set(15, false);
+        batch(&mut controller, 0);
+
+        controller.reset();
+        assert!(!controller.is_held(11));
+        assert_eq!(
+            batch(&mut controller, 1),
+            vec![(11, false), (13, false), (14, false)]
+        );
+        // the pulse does not release the switch again later on
+        assert_eq!(batch(&mut controller, 4), vec![]);
+    }
+}
@@ -0,0 +1,121 @@
+use std::{path::Path, time::Duration};
+
+use log::info;
+use serde::Deserialize;
+
+use crate::switch_control::{Debounce, SwitchController};
+
+const DEFAULT_PULSE_MS: u64 = 100;
+
+// Scripted switch changes, for repeatable tests. A script is a toml file like
+//
+//   [[steps]]
+//   at_ms = 5000
+//   close = [32, 33, 34, 35]
+//
+//   [[steps]]
+//   at_ms = 8000
+//   pulse = [13]
+//   pulse_ms = 50
+//
+// Times are in emulated time since the rom started. Steps run in the order of their time,
+// all changes of a step go to pinmame together.
+#[derive(Debug, Default, Deserialize)]
+pub struct SwitchScript {
+    #[serde(default)]
+    pub debounce: Option<DebounceConfig>,
+    #[serde(default)]
+    pub steps: Vec<Step>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DebounceConfig {
+    pub bounces: u32,
+    pub interval_ms: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Step {
+    pub at_ms: u64,
+    #[serde(default)]
+    pub close: Vec<u32>,
+    #[serde(default)]
+    pub open: Vec<u32>,
+    #[serde(default)]
+    pub pulse: Vec<u32>,
+    pub pulse_ms: Option<u64>,
+    #[serde(default)]
+    pub hold: Vec<u32>,
+    #[serde(default)]
+    pub release: Vec<u32>,
+}
+
+pub struct SwitchScriptRunner {
+    steps: Vec<Step>,
+    next: usize,
+    // emulated time the rom (re)started at
+    started: Duration,
+}
+
+impl SwitchScript {
+    pub fn load(path: &Path) -> Result<SwitchScript, String> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+        toml::from_str(&content).map_err(|e| format!("Could not parse {}: {}", path.display(), e))
+    }
+
+    pub fn start(mut self, controller: &mut SwitchController) -> SwitchScriptRunner {
+        if let Some(debounce) = &self.debounce {
+            controller.set_debounce(Some(Debounce {
+                bounces: debounce.bounces,
+                interval: Duration::from_millis(debounce.interval_ms),
+            }));
+        }
+        self.steps.sort_by_key(|step| step.at_ms);
+        SwitchScriptRunner {
+            steps: self.steps,
+            next: 0,
+            started: Duration::ZERO,
+        }
+    }
+}
+
+impl SwitchScriptRunner {
+    // Queues the steps that are due on the controller, call before `SwitchController::update`
+    pub fn update(&mut self, now: Duration, controller: &mut SwitchController) {
+        while let Some(step) = self.steps.get(self.next) {
+            if self.started + Duration::from_millis(step.at_ms) > now {
+                break;
+            }
+            info!("Switch script step at {}ms", step.at_ms);
+            let changes: Vec<(u32, bool)> = step
+                .close
+                .iter()
+                .map(|switch| (*switch, true))
+                .chain(step.open.iter().map(|switch| (*switch, false)))
+                .collect();
+            controller.set_many(&changes);
+            let pulse = Duration::from_millis(step.pulse_ms.unwrap_or(DEFAULT_PULSE_MS));
+            for switch in &step.pulse {
+                controller.pulse(*switch, pulse);
+            }
+            for switch in &step.hold {
+                controller.hold(*switch);
+            }
+            for switch in &step.release {
+                controller.release(*switch);
+            }
+            self.next += 1;
+        }
+    }
+
+    // Runs the script again from the start, for a rom that was reset at `now`
+    pub fn restart(&mut self, now: Duration) {
+        self.next = 0;
+        self.started = now;
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.next >= self.steps.len()
+    }
+}
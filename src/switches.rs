@@ -1,19 +1,139 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, time::Duration};
 
-use indexmap::IndexMap;
 use sdl2::{pixels::Color, rect::Rect};
 
+use crate::{db::SwitchIndex, pinmame, switch_control::SwitchController, text::render_text};
+
 const SWITCH_WIDTH: u32 = 140;
 const SWITCH_HEIGHT: u32 = 20;
 const MARGIN: u32 = 2;
 
+// The matrix view shows the switches like the game manual does: the dedicated (direct)
+// switches, the 8x8 matrix with number = column * 10 + row and the flipper switches.
+const MATRIX_CELL_WIDTH: u32 = 54;
+const MATRIX_CELL_HEIGHT: u32 = 20;
+const MATRIX_HEADER_HEIGHT: u32 = 14;
+const MATRIX_COLUMNS: [&str; 10] = ["D", "1", "2", "3", "4", "5", "6", "7", "8", "F"];
+const FLIPPER_SWITCH_COLUMN: u32 = 11;
+
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum SwitchView {
+    #[default]
+    List,
+    Matrix,
+}
+
+impl SwitchView {
+    pub fn toggled(self) -> SwitchView {
+        match self {
+            SwitchView::List => SwitchView::Matrix,
+            SwitchView::Matrix => SwitchView::List,
+        }
+    }
+}
+
+// What we know about the switches, the actual state is read back from pinmame every frame
+// as the rom or the mech handling can change switches without us knowing.
+#[derive(Default)]
+pub struct SwitchStates {
+    actual: HashMap<u32, bool>,
+    // switches the user holds or toggled
+    forced: HashMap<u32, bool>,
+    controller: SwitchController,
+}
+
+impl SwitchStates {
+    pub fn controller(&mut self) -> &mut SwitchController {
+        &mut self.controller
+    }
+
+    // Sends the pending changes to pinmame and reads back the state of all switches
+    pub fn update(&mut self, now: Duration, switches: &SwitchIndex) {
+        self.controller.update(now);
+        self.poll(switches);
+    }
+
+    fn poll(&mut self, switches: &SwitchIndex) {
+        for switch in matrix_switches().chain(switches.keys().copied()) {
+            self.actual
+                .insert(switch, pinmame::get_switch(switch as i32) != 0);
+        }
+    }
+
+    pub fn is_closed(&self, switch: u32) -> bool {
+        self.actual.get(&switch).copied().unwrap_or(false)
+    }
+
+    pub fn is_forced(&self, switch: u32) -> bool {
+        self.forced.contains_key(&switch) || self.controller.is_held(switch)
+    }
+
+    pub fn force(&mut self, switch: u32, closed: bool) {
+        self.forced.insert(switch, closed);
+        self.actual.insert(switch, closed);
+        self.controller.set(switch, closed);
+    }
+
+    // closed for as long as the mouse button is down
+    pub fn hold(&mut self, switch: u32) {
+        self.actual.insert(switch, true);
+        self.controller.hold(switch);
+    }
+
+    // gives the switch back to the emulator after setting it open
+    pub fn release(&mut self, switch: u32) {
+        self.forced.remove(&switch);
+        self.actual.insert(switch, false);
+        self.controller.release(switch);
+    }
+
+    // gives all switches back to the emulator
+    pub fn reset(&mut self) {
+        self.forced.clear();
+        self.controller.reset();
+    }
+
+    pub fn toggle(&mut self, switch: u32) -> bool {
+        let closed = !self.is_closed(switch);
+        self.force(switch, closed);
+        closed
+    }
+}
+
+fn switch_color(closed: bool, named: bool) -> Color {
+    match (closed, named) {
+        (true, _) => Color::RGB(100, 40, 40),
+        (false, true) => Color::RGB(40, 10, 10),
+        (false, false) => Color::RGB(20, 20, 20),
+    }
+}
+
+// forced switches get an outline so they stand out from the ones the emulator drives
+fn render_switch_button(
+    rect: Rect,
+    switch: u32,
+    label: &str,
+    named: bool,
+    switch_states: &SwitchStates,
+    canvas: &mut sdl2::render::Canvas<sdl2::video::Window>,
+    font: &sdl2::ttf::Font<'_, '_>,
+) -> Result<(), String> {
+    canvas.set_draw_color(switch_color(switch_states.is_closed(switch), named));
+    canvas.fill_rect(rect)?;
+    if switch_states.is_forced(switch) {
+        canvas.set_draw_color(Color::RGB(80, 140, 220));
+        canvas.draw_rect(rect)?;
+    }
+    render_label(rect, label, canvas, font)
+}
+
 pub fn render_switches(
     at_x: u32,
     at_y: u32,
     canvas: &mut sdl2::render::Canvas<sdl2::video::Window>,
     font: &sdl2::ttf::Font<'_, '_>,
-    switches: &IndexMap<u32, &str>,
-    switch_states: &HashMap<u32, bool>,
+    switches: &SwitchIndex,
+    switch_states: &SwitchStates,
 ) -> Result<(), String> {
     for (index, (code, label)) in switches.iter().enumerate() {
         let x = (index % 5) as u32;
@@ -24,52 +144,13 @@ pub fn render_switches(
             SWITCH_WIDTH,
             SWITCH_HEIGHT,
         );
-        let button_color = match switch_states.get(code) {
-            Some(true) => Color::RGB(100, 40, 40),
-            Some(false) => Color::RGB(40, 10, 10),
-            None => Color::RGB(20, 20, 20),
-        };
-        canvas.set_draw_color(button_color);
-        canvas.fill_rect(rect)?;
-
-        let font_color = Color::RGBA(200, 200, 200, 255);
-
-        let texture_creator = canvas.texture_creator();
-        // render a surface, and convert it to a texture bound to the canvas
-        // TODO we need to cache these textures
         let label = format!("{}: {}", code, label);
-        let surface = font
-            .render(&label)
-            .blended(font_color)
-            .map_err(|e| e.to_string())?;
-        let texture = texture_creator
-            .create_texture_from_surface(&surface)
-            .map_err(|e| e.to_string())?;
-
-        // min of font width or switch width (scaled)
-        let min_width = std::cmp::min(
-            surface.width(),
-            (SWITCH_WIDTH as f32 * canvas.scale().0) as u32,
-        );
-        let min_height = std::cmp::min(
-            surface.height(),
-            (SWITCH_HEIGHT as f32 * canvas.scale().1) as u32,
-        );
-        let src = Rect::new(0, 0, min_width, min_height);
-
-        let target = Rect::new(
-            (at_x + x * (SWITCH_WIDTH + MARGIN)) as i32,
-            (at_y + y * (SWITCH_HEIGHT + MARGIN)) as i32,
-            (min_width as f32 / canvas.scale().1) as u32,
-            (min_height as f32 / canvas.scale().1) as u32,
-        );
-
-        canvas.copy(&texture, Some(src), Some(target))?;
+        render_switch_button(rect, *code, &label, true, switch_states, canvas, font)?;
     }
     Ok(())
 }
 
-pub fn switch_id_for_mouse(x: i32, y: i32, switches: &IndexMap<u32, &str>) -> Option<u32> {
+pub fn switch_id_for_mouse(x: i32, y: i32, switches: &SwitchIndex) -> Option<u32> {
     let x = x / (SWITCH_WIDTH + MARGIN) as i32;
     let y = y / (SWITCH_HEIGHT + MARGIN) as i32;
 
@@ -81,3 +162,129 @@ pub fn switch_id_for_mouse(x: i32, y: i32, switches: &IndexMap<u32, &str>) -> Op
         None
     }
 }
+
+// Draws the label clipped to the button
+fn render_label(
+    rect: Rect,
+    label: &str,
+    canvas: &mut sdl2::render::Canvas<sdl2::video::Window>,
+    font: &sdl2::ttf::Font<'_, '_>,
+) -> Result<(), String> {
+    let font_color = Color::RGBA(200, 200, 200, 255);
+
+    let texture_creator = canvas.texture_creator();
+    // render a surface, and convert it to a texture bound to the canvas
+    // TODO we need to cache these textures
+    let surface = font
+        .render(label)
+        .blended(font_color)
+        .map_err(|e| e.to_string())?;
+    let texture = texture_creator
+        .create_texture_from_surface(&surface)
+        .map_err(|e| e.to_string())?;
+
+    // min of font width or switch width (scaled)
+    let min_width = std::cmp::min(
+        surface.width(),
+        (rect.width() as f32 * canvas.scale().0) as u32,
+    );
+    let min_height = std::cmp::min(
+        surface.height(),
+        (rect.height() as f32 * canvas.scale().1) as u32,
+    );
+    let src = Rect::new(0, 0, min_width, min_height);
+
+    let target = Rect::new(
+        rect.x(),
+        rect.y(),
+        (min_width as f32 / canvas.scale().1) as u32,
+        (min_height as f32 / canvas.scale().1) as u32,
+    );
+
+    canvas.copy(&texture, Some(src), Some(target))
+}
+
+// Switch number for a column (0 is the dedicated column, 9 the flipper column) and row (1-8)
+fn matrix_switch(column: u32, row: u32) -> u32 {
+    match column {
+        0 => row,
+        9 => FLIPPER_SWITCH_COLUMN * 10 + row,
+        _ => column * 10 + row,
+    }
+}
+
+// All switches shown in the matrix view
+fn matrix_switches() -> impl Iterator<Item = u32> {
+    (0..MATRIX_COLUMNS.len() as u32)
+        .flat_map(|column| (1..=8).map(move |row| matrix_switch(column, row)))
+}
+
+pub fn render_switch_matrix(
+    at_x: u32,
+    at_y: u32,
+    canvas: &mut sdl2::render::Canvas<sdl2::video::Window>,
+    font: &sdl2::ttf::Font<'_, '_>,
+    switches: &SwitchIndex,
+    switch_states: &SwitchStates,
+) -> Result<(), String> {
+    for (column, header) in MATRIX_COLUMNS.iter().enumerate() {
+        let x = at_x + column as u32 * (MATRIX_CELL_WIDTH + MARGIN);
+        render_text(
+            x as i32,
+            at_y as i32,
+            header,
+            Color::RGB(150, 150, 150),
+            canvas,
+            font,
+        )?;
+        for row in 1..=8 {
+            let switch = matrix_switch(column as u32, row);
+            let rect = Rect::new(
+                x as i32,
+                (at_y + MATRIX_HEADER_HEIGHT + (row - 1) * (MATRIX_CELL_HEIGHT + MARGIN)) as i32,
+                MATRIX_CELL_WIDTH,
+                MATRIX_CELL_HEIGHT,
+            );
+            let name = switches.get(&switch);
+            let label = match name {
+                Some(name) => format!("{} {}", switch, name),
+                None => switch.to_string(),
+            };
+            render_switch_button(
+                rect,
+                switch,
+                &label,
+                name.is_some(),
+                switch_states,
+                canvas,
+                font,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+pub fn matrix_switch_id_for_mouse(x: i32, y: i32) -> Option<u32> {
+    let y = y - MATRIX_HEADER_HEIGHT as i32;
+    if x < 0 || y < 0 {
+        return None;
+    }
+    let column = x as u32 / (MATRIX_CELL_WIDTH + MARGIN);
+    let row = y as u32 / (MATRIX_CELL_HEIGHT + MARGIN) + 1;
+    if column >= MATRIX_COLUMNS.len() as u32 || row > 8 {
+        return None;
+    }
+    Some(matrix_switch(column, row))
+}
+
+pub fn switch_id_for_mouse_in_view(
+    view: SwitchView,
+    x: i32,
+    y: i32,
+    switches: &SwitchIndex,
+) -> Option<u32> {
+    match view {
+        SwitchView::List => switch_id_for_mouse(x, y, switches),
+        SwitchView::Matrix => matrix_switch_id_for_mouse(x, y),
+    }
+}
@@ -0,0 +1,73 @@
+use sdl2::{pixels::Color, rect::Rect};
+
+// Renders a single line of text with its top left corner at x, y.
+// Returns the size the text took on the canvas.
+pub fn render_text(
+    x: i32,
+    y: i32,
+    text: &str,
+    color: Color,
+    canvas: &mut sdl2::render::Canvas<sdl2::video::Window>,
+    font: &sdl2::ttf::Font<'_, '_>,
+) -> Result<(u32, u32), String> {
+    if text.is_empty() {
+        return Ok((0, 0));
+    }
+    let texture_creator = canvas.texture_creator();
+    // TODO we need to cache these textures
+    let surface = font
+        .render(text)
+        .blended(color)
+        .map_err(|e| e.to_string())?;
+    let texture = texture_creator
+        .create_texture_from_surface(&surface)
+        .map_err(|e| e.to_string())?;
+    // the font is loaded at the canvas scale, undo that when placing it
+    let width = (surface.width() as f32 / canvas.scale().0) as u32;
+    let height = (surface.height() as f32 / canvas.scale().1) as u32;
+    canvas.copy(&texture, None, Some(Rect::new(x, y, width, height)))?;
+    Ok((width, height))
+}
+
+// Renders a box with some lines of text next to the given position, kept inside the canvas
+pub fn render_tooltip(
+    x: i32,
+    y: i32,
+    lines: &[String],
+    canvas: &mut sdl2::render::Canvas<sdl2::video::Window>,
+    font: &sdl2::ttf::Font<'_, '_>,
+) -> Result<(), String> {
+    const PADDING: u32 = 4;
+    let mut width = 0;
+    let mut height = 0;
+    for line in lines {
+        let (w, h) = font.size_of(line).map_err(|e| e.to_string())?;
+        width = width.max((w as f32 / canvas.scale().0) as u32);
+        height += (h as f32 / canvas.scale().1) as u32;
+    }
+    let width = width + 2 * PADDING;
+    let height = height + 2 * PADDING;
+
+    let viewport = canvas.viewport();
+    let x = x.min(viewport.width() as i32 - width as i32).max(0);
+    let y = y.min(viewport.height() as i32 - height as i32).max(0);
+    let rect = Rect::new(x, y, width, height);
+    canvas.set_draw_color(Color::RGB(40, 40, 50));
+    canvas.fill_rect(rect)?;
+    canvas.set_draw_color(Color::RGB(120, 120, 140));
+    canvas.draw_rect(rect)?;
+
+    let mut line_y = y + PADDING as i32;
+    for line in lines {
+        let (_, h) = render_text(
+            x + PADDING as i32,
+            line_y,
+            line,
+            Color::RGB(220, 220, 220),
+            canvas,
+            font,
+        )?;
+        line_y += h as i32;
+    }
+    Ok(())
+}
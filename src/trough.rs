@@ -0,0 +1,149 @@
+use log::info;
+
+use crate::{db::Trough, switch_control::SwitchController};
+
+// Moves balls around based on the solenoids the rom fires, so a game can be started
+// without a machine: trough -> shooter lane -> playfield -> outhole -> back into the trough.
+pub struct BallTrough {
+    config: Trough,
+    in_trough: usize,
+    in_shooter: bool,
+    on_playfield: usize,
+    in_outhole: usize,
+}
+
+impl BallTrough {
+    pub fn new(config: Trough) -> BallTrough {
+        let balls = config
+            .balls
+            .map(|balls| balls as usize)
+            .unwrap_or(config.switches.len());
+        BallTrough {
+            config,
+            in_trough: balls,
+            in_shooter: false,
+            on_playfield: 0,
+            in_outhole: 0,
+        }
+    }
+
+    pub fn start(&self, controller: &mut SwitchController) {
+        info!("Ball trough with {} balls", self.in_trough);
+        // after a reset the shooter lane might still hold a ball
+        if let Some(shooter_switch) = self.config.shooter_switch {
+            controller.set(shooter_switch, self.in_shooter);
+        }
+        if let Some(outhole_switch) = self.config.outhole_switch {
+            controller.set(outhole_switch, self.in_outhole > 0);
+        }
+        self.update_trough_switches(controller);
+    }
+
+    // Call for every solenoid change
+    pub fn on_solenoid(
+        &mut self,
+        sol_no: u32,
+        was_on: bool,
+        on: bool,
+        controller: &mut SwitchController,
+    ) {
+        if was_on || !on {
+            return;
+        }
+        if sol_no == self.config.eject_coil {
+            self.eject(controller);
+        } else if Some(sol_no) == self.config.launch_coil {
+            self.launch(controller);
+        } else if Some(sol_no) == self.config.outhole_coil {
+            self.kick_outhole(controller);
+        }
+    }
+
+    fn eject(&mut self, controller: &mut SwitchController) {
+        // the rom might fire the coil again when the shooter lane is not cleared
+        if self.in_trough == 0 || self.in_shooter {
+            return;
+        }
+        self.in_trough -= 1;
+        self.update_trough_switches(controller);
+        match self.config.shooter_switch {
+            Some(shooter_switch) => {
+                info!("Ball ejected to the shooter lane");
+                self.in_shooter = true;
+                controller.set(shooter_switch, true);
+            }
+            None => {
+                info!("Ball ejected to the playfield");
+                self.on_playfield += 1;
+            }
+        }
+    }
+
+    pub fn launch(&mut self, controller: &mut SwitchController) {
+        if !self.in_shooter {
+            return;
+        }
+        info!("Ball launched");
+        self.in_shooter = false;
+        if let Some(shooter_switch) = self.config.shooter_switch {
+            controller.set(shooter_switch, false);
+        }
+        self.on_playfield += 1;
+    }
+
+    pub fn drain(&mut self, controller: &mut SwitchController) {
+        if self.on_playfield == 0 {
+            return;
+        }
+        self.on_playfield -= 1;
+        match self.config.outhole_switch {
+            Some(outhole_switch) => {
+                info!("Ball drained to the outhole");
+                self.in_outhole += 1;
+                controller.set(outhole_switch, true);
+            }
+            None => {
+                info!("Ball drained");
+                self.in_trough += 1;
+                self.update_trough_switches(controller);
+            }
+        }
+    }
+
+    fn kick_outhole(&mut self, controller: &mut SwitchController) {
+        if self.in_outhole == 0 {
+            return;
+        }
+        info!("Ball kicked from the outhole into the trough");
+        self.in_outhole -= 1;
+        self.in_trough += 1;
+        if let Some(outhole_switch) = self.config.outhole_switch {
+            controller.set(outhole_switch, self.in_outhole > 0);
+        }
+        self.update_trough_switches(controller);
+    }
+
+    // balls roll down to the eject side
+    fn update_trough_switches(&self, controller: &mut SwitchController) {
+        let changes: Vec<(u32, bool)> = self
+            .config
+            .switches
+            .iter()
+            .enumerate()
+            .map(|(index, switch)| (*switch, index < self.in_trough))
+            .collect();
+        controller.set_many(&changes);
+    }
+
+    pub fn status(&self) -> Vec<String> {
+        let mut status = vec![
+            format!("Trough: {}", self.in_trough),
+            format!("Shooter: {}", if self.in_shooter { 1 } else { 0 }),
+            format!("Playfield: {}", self.on_playfield),
+        ];
+        if self.config.outhole_switch.is_some() {
+            status.push(format!("Outhole: {}", self.in_outhole));
+        }
+        status
+    }
+}
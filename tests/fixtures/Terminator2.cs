@@ -0,0 +1,28 @@
+namespace VisualPinball.Engine.PinMAME.Games
+{
+	public class Terminator2 : PinMameGame
+	{
+		public override GamelogicEngineSwitch[] AvailableSwitches { get; } = {
+			new GamelogicEngineSwitch("11") { Description = "Right Flipper", InputActionHint = InputConstants.ActionRightFlipper },
+			new GamelogicEngineSwitch("12") { Description = "Left \"Flipper\"" },
+			new GamelogicEngineSwitch("sw") { Description = "Not a number" },
+		};
+
+		public override GamelogicEngineCoil[] AvailableCoils { get; } = {
+			new GamelogicEngineCoil("01") { Description = "Plunger" },
+			new GamelogicEngineCoil("11") { Description = "Gun Motor" },
+		};
+
+		public override GamelogicEngineLamp[] AvailableLamps { get; } = {
+			new GamelogicEngineLamp("11") { Description = "Super Jackpot" },
+		};
+
+		private void SetupMechs(PinMame pinMame)
+		{
+			var gun = new PinMameMechConfig(PinMameMechFlag.NonLinear | PinMameMechFlag.Reverse | PinMameMechFlag.OneSol, 11, 240, 240, 0, 0, 0);
+			gun.AddSwitch(new PinMameMechSwitchConfig(33, 0, 5));
+			gun.AddSwitch(new PinMameMechSwitchConfig(32, 98, 105, 2));
+			pinMame.SetMech(0, gun);
+		}
+	}
+}